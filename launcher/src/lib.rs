@@ -0,0 +1,10 @@
+use dcpl::registry::Registry;
+
+/// Every language the `dcpl` binaries know about. The first is the
+/// default.
+pub fn registry() -> Registry {
+    let mut registry = Registry::new();
+    registry.register(postfix::registration());
+    registry.register(lisp::registration());
+    registry
+}
@@ -0,0 +1,14 @@
+use std::io;
+use std::process;
+
+use dcpl::lsp::LspServer;
+
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut server = LspServer::new(launcher::registry());
+    if let Err(error) = server.serve(stdin.lock(), stdout.lock()) {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
+}
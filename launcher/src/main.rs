@@ -0,0 +1,213 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::process;
+
+use dcpl::cli::{self, Mode};
+use dcpl::cst;
+use dcpl::registry::{Polyglot, Registry};
+use dcpl::InitFile;
+
+use launcher::registry;
+
+const USAGE: &str =
+    "usage: dcpl [--lang <name>] [--init <file>] [--record <file>] [--rpc] [file]\n       dcpl fmt [--check] [file...]";
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    lang: Option<String>,
+    file: Option<String>,
+    /// The options every language binary takes.
+    cli: cli::Options,
+}
+
+/// The launcher's own `--lang` and file, around the options every
+/// language binary takes. Only `--rpc` of their modes is allowed, since
+/// the sessions of `--listen` and `--socket` each need a language they
+/// can move to their own thread.
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut lang = None;
+    let mut file = None;
+    let cli = cli::parse_args_with(args, |arg, rest| {
+        match arg {
+            "--lang" => match rest.next() {
+                Some(name) => lang = Some(name.clone()),
+                None => return Err("`--lang` needs a language name".to_string()),
+            },
+            _ if arg.starts_with("--") || file.is_some() => return Err(USAGE.to_string()),
+            _ => file = Some(arg.to_string()),
+        }
+        Ok(())
+    })?;
+    match cli.mode {
+        Mode::Repl => {}
+        Mode::Rpc if file.is_none() => {}
+        Mode::Rpc => return Err("give either a file or `--rpc`".to_string()),
+        _ => return Err("`--listen` and `--socket` need a language's own binary".to_string()),
+    }
+    Ok(Options { lang, file, cli })
+}
+
+/// The language to start with: the one asked for, or the one for the
+/// file's extension, or else the first one registered.
+fn language_name(registry: &Registry, options: &Options) -> Result<String, String> {
+    if let Some(name) = &options.lang {
+        return Ok(name.clone());
+    }
+    match &options.file {
+        Some(file) => match registry.for_path(file) {
+            Some(name) => Ok(name.to_string()),
+            None => Err(format!(
+                "Don't know what language `{}` is in, try --lang",
+                file
+            )),
+        },
+        None => Ok(registry.registrations()[0].name().to_string()),
+    }
+}
+
+/// `dcpl fmt [--check] [file...]` reformats each file in place, or
+/// stdin to stdout. With `--check` it lists the files that would change
+/// instead, and fails if there are any.
+fn format_files(args: &[String]) -> Result<i32, String> {
+    let check = args.iter().any(|arg| arg == "--check");
+    let paths: Vec<&String> = args.iter().filter(|arg| *arg != "--check").collect();
+    if paths.iter().any(|arg| arg.starts_with("--")) {
+        return Err(USAGE.to_string());
+    }
+
+    if paths.is_empty() {
+        let mut text = String::new();
+        io::stdin()
+            .read_to_string(&mut text)
+            .map_err(|error| error.to_string())?;
+        let formatted = cst::format(&text).map_err(|error| format!("<stdin>: {}", error))?;
+        if check {
+            return Ok(if formatted == text { 0 } else { 1 });
+        }
+        print!("{}", formatted);
+        return Ok(0);
+    }
+
+    let mut code = 0;
+    for path in paths {
+        let result = fs::read_to_string(path)
+            .map_err(|error| error.to_string())
+            .and_then(|text| {
+                let formatted = cst::format(&text).map_err(|error| error.to_string())?;
+                Ok((text, formatted))
+            });
+        let (text, formatted) = match result {
+            Ok(texts) => texts,
+            Err(error) => {
+                eprintln!("error: {}: {}", path, error);
+                code = 1;
+                continue;
+            }
+        };
+        if formatted == text {
+            continue;
+        }
+        if check {
+            println!("{}", path);
+            code = 1;
+        } else if let Err(error) = fs::write(path, formatted) {
+            eprintln!("error: {}: {}", path, error);
+            code = 1;
+        }
+    }
+    Ok(code)
+}
+
+fn run(args: &[String]) -> Result<i32, String> {
+    if args.first().map(String::as_str) == Some("fmt") {
+        return format_files(&args[1..]);
+    }
+    let options = parse_args(args)?;
+    let registry = registry();
+    let name = language_name(&registry, &options)?;
+    let mut interpreter = Polyglot::new(registry, &name)?.into_interpreter();
+    if let Some(path) = &options.cli.init {
+        interpreter.set_init(InitFile::Path(path.into()));
+    }
+    if let Some(path) = &options.cli.record {
+        interpreter.record(path)?;
+    }
+    match &options.file {
+        Some(path) => {
+            let file = File::open(path).map_err(|error| format!("{}: {}", path, error))?;
+            Ok(interpreter.run_script(BufReader::new(file)))
+        }
+        None if options.cli.mode == Mode::Rpc => Ok(interpreter.run_rpc()),
+        None => Ok(interpreter.run()),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let code = run(&args).unwrap_or_else(|error| {
+        eprintln!("error: {}", error);
+        1
+    });
+    process::exit(code);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn options(args: &[&str]) -> Result<Options, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        parse_args(&args)
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let repl = cli::Options {
+            init: None,
+            record: None,
+            mode: Mode::Repl,
+        };
+        assert_eq!(
+            Ok(Options {
+                lang: None,
+                file: None,
+                cli: repl,
+            }),
+            options(&[])
+        );
+        assert_eq!(
+            Ok(Options {
+                lang: Some("lisp".to_string()),
+                file: Some("x.pf".to_string()),
+                cli: cli::Options {
+                    init: Some("defs.rc".to_string()),
+                    record: None,
+                    mode: Mode::Repl,
+                },
+            }),
+            options(&["--lang", "lisp", "--init", "defs.rc", "x.pf"])
+        );
+        assert_eq!(Mode::Rpc, options(&["--rpc"]).unwrap().cli.mode);
+        assert_eq!(
+            Err("`--init` needs a file name".to_string()),
+            options(&["--init"])
+        );
+        assert!(options(&["--lang"]).is_err());
+        assert!(options(&["a.pf", "b.pf"]).is_err());
+        assert!(options(&["--verbose"]).is_err());
+        assert!(options(&["--rpc", "a.pf"]).is_err());
+        assert!(options(&["--listen", "localhost:4000"]).is_err());
+    }
+
+    #[test]
+    fn test_language_name() {
+        let registry = registry();
+        let name = |args: &[&str]| language_name(&registry, &options(args).unwrap());
+        assert_eq!(Ok("postfix".to_string()), name(&[]));
+        assert_eq!(Ok("lisp".to_string()), name(&["prog.lisp"]));
+        assert_eq!(Ok("postfix".to_string()), name(&["prog.pf"]));
+        assert_eq!(Ok("lisp".to_string()), name(&["--lang", "lisp", "prog.pf"]));
+        assert!(name(&["prog.txt"]).is_err());
+    }
+}
@@ -0,0 +1,53 @@
+//! Runs `dcpl fmt` on files the way a user would.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn dcpl(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_dcpl"))
+        .args(args)
+        .output()
+        .expect("failed to run dcpl")
+}
+
+fn temp_file(name: &str, text: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("dcpl-fmt-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    fs::write(&path, text).unwrap();
+    path
+}
+
+#[test]
+fn test_fmt_rewrites_files() {
+    let path = temp_file(
+        "sq.pfx",
+        ";; Squares\n(def sq 1   (1 nget\n mul))  ;; done\n",
+    );
+    let path_str = path.to_str().unwrap();
+
+    let output = dcpl(&["fmt", "--check", path_str]);
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!(
+        format!("{}\n", path_str),
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    assert!(dcpl(&["fmt", path_str]).status.success());
+    assert_eq!(
+        ";; Squares\n(def sq 1 (1 nget mul)) ;; done\n",
+        fs::read_to_string(&path).unwrap()
+    );
+    assert!(dcpl(&["fmt", "--check", path_str]).status.success());
+}
+
+#[test]
+fn test_fmt_reports_syntax_errors() {
+    let path = temp_file("broken.lisp", "(set! a\n");
+    let output = dcpl(&["fmt", path.to_str().unwrap()]);
+    assert_eq!(Some(1), output.status.code());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("line 2"));
+    assert_eq!("(set! a\n", fs::read_to_string(&path).unwrap());
+}
@@ -0,0 +1,190 @@
+//! Drives `dcpl-lsp` over stdio the way an editor would.
+
+use std::io::{BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use dcpl::lsp::{read_message, write_message};
+use serde_json::{json, Value};
+
+struct Client {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: i64,
+}
+
+impl Client {
+    fn start() -> Client {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_dcpl-lsp"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("failed to start dcpl-lsp");
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut client = Client {
+            child,
+            stdin,
+            stdout,
+            next_id: 1,
+        };
+        let result = client.request("initialize", json!({ "capabilities": {} }));
+        assert_eq!(json!(true), result["capabilities"]["hoverProvider"]);
+        client.notify("initialized", json!({}));
+        client
+    }
+
+    fn send(&mut self, message: Value) {
+        write_message(&mut self.stdin, &message).unwrap();
+    }
+
+    fn receive(&mut self) -> Value {
+        let message = read_message(&mut self.stdout).unwrap().expect("no message");
+        serde_json::from_str(&message).unwrap()
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+        let response = self.receive();
+        assert_eq!(json!(id), response["id"]);
+        response["result"].clone()
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    /// Open a document and return the diagnostics published for it.
+    fn open(&mut self, uri: &str, text: &str) -> Value {
+        let document = json!({ "uri": uri, "languageId": "dcpl", "version": 1, "text": text });
+        self.notify("textDocument/didOpen", json!({ "textDocument": document }));
+        self.diagnostics(uri)
+    }
+
+    fn change(&mut self, uri: &str, text: &str) -> Value {
+        let params = json!({
+            "textDocument": { "uri": uri, "version": 2 },
+            "contentChanges": [{ "text": text }],
+        });
+        self.notify("textDocument/didChange", params);
+        self.diagnostics(uri)
+    }
+
+    fn diagnostics(&mut self, uri: &str) -> Value {
+        let message = self.receive();
+        assert_eq!(json!("textDocument/publishDiagnostics"), message["method"]);
+        assert_eq!(json!(uri), message["params"]["uri"]);
+        message["params"]["diagnostics"].clone()
+    }
+
+    fn at(&mut self, method: &str, uri: &str, line: u64, character: u64) -> Value {
+        let params = json!({
+            "textDocument": { "uri": uri },
+            "position": { "line": line, "character": character },
+        });
+        self.request(method, params)
+    }
+
+    fn finish(mut self) {
+        assert_eq!(Value::Null, self.request("shutdown", Value::Null));
+        self.notify("exit", Value::Null);
+        self.stdin.flush().unwrap();
+        drop(self.stdin);
+        assert!(self.child.wait().unwrap().success());
+    }
+}
+
+fn range(start: (u64, u64), end: (u64, u64)) -> Value {
+    json!({
+        "start": { "line": start.0, "character": start.1 },
+        "end": { "line": end.0, "character": end.1 },
+    })
+}
+
+const POSTFIX: &str = "(def sq 1 (1 nget mul))\n(sq 3)\n";
+
+#[test]
+fn test_diagnostics() {
+    let mut client = Client::start();
+    assert_eq!(json!([]), client.open("file:///a.pfx", POSTFIX));
+
+    let diagnostics = client.change("file:///a.pfx", "(def sq 1\n  (1 nget mul)\n");
+    assert_eq!(1, diagnostics.as_array().unwrap().len());
+    assert_eq!(json!(1), diagnostics[0]["severity"]);
+    assert_eq!(json!(2), diagnostics[0]["range"]["start"]["line"]);
+
+    assert_eq!(json!([]), client.change("file:///a.pfx", POSTFIX));
+    client.finish();
+}
+
+#[test]
+fn test_hover() {
+    let mut client = Client::start();
+    client.open("file:///a.pfx", POSTFIX);
+
+    let hover = client.at("textDocument/hover", "file:///a.pfx", 0, 19);
+    let text = hover["contents"]["value"].as_str().unwrap();
+    assert!(text.contains("mul"), "{}", text);
+
+    let hover = client.at("textDocument/hover", "file:///a.pfx", 1, 2);
+    assert_eq!(json!("(def sq 1 (1 nget mul))"), hover["contents"]["value"]);
+
+    let hover = client.at("textDocument/hover", "file:///a.pfx", 1, 4);
+    assert_eq!(Value::Null, hover);
+
+    client.open("file:///b.lisp", "(set! x (quote 1))\n");
+    let hover = client.at("textDocument/hover", "file:///b.lisp", 0, 10);
+    assert!(hover["contents"]["value"]
+        .as_str()
+        .unwrap()
+        .contains("quote"));
+    client.finish();
+}
+
+#[test]
+fn test_definition() {
+    let mut client = Client::start();
+    client.open("file:///a.pfx", POSTFIX);
+    let location = client.at("textDocument/definition", "file:///a.pfx", 1, 2);
+    assert_eq!(
+        json!({ "uri": "file:///a.pfx", "range": range((0, 5), (0, 7)) }),
+        location
+    );
+
+    let lisp = "(set! double (lambda (x) (add x x)))\n(double 2)\n";
+    client.open("file:///b.lisp", lisp);
+    let location = client.at("textDocument/definition", "file:///b.lisp", 1, 3);
+    assert_eq!(range((0, 6), (0, 12)), location["range"]);
+    client.finish();
+}
+
+#[test]
+fn test_document_symbols() {
+    let mut client = Client::start();
+    client.open("file:///b.lisp", "(set! a 1)\n\n(set! b (quote (a)))\n");
+    let symbols = client.request(
+        "textDocument/documentSymbol",
+        json!({ "textDocument": { "uri": "file:///b.lisp" } }),
+    );
+    let names: Vec<&str> = symbols
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|symbol| symbol["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(vec!["a", "b"], names);
+    assert_eq!(range((2, 0), (2, 20)), symbols[1]["range"]);
+    assert_eq!(range((2, 6), (2, 7)), symbols[1]["selectionRange"]);
+    client.finish();
+}
+
+#[test]
+fn test_unknown_method() {
+    let mut client = Client::start();
+    client.send(json!({ "jsonrpc": "2.0", "id": 99, "method": "workspace/frobnicate" }));
+    let response = client.receive();
+    assert_eq!(json!(-32601), response["error"]["code"]);
+    client.finish();
+}
@@ -1,32 +1,49 @@
 use std::collections::HashMap;
 
-use dcpl::SExp;
+use dcpl::{Interrupt, Language, SExp};
 
-use crate::{Env, Error, Integer, LispFn, List, Value};
+use crate::{Env, Error, Integer, LispFn, List, Symbol, Value};
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug)]
 pub struct Runtime {
     env: Env,
+    interrupt: Interrupt,
+}
+
+impl Default for Runtime {
+    fn default() -> Runtime {
+        Runtime::new()
+    }
 }
 
 impl Runtime {
     pub fn new() -> Runtime {
         let env = Env(HashMap::new());
-        Runtime { env }
+        Runtime::new_with_env(env)
     }
 
     pub fn new_with_env(env: Env) -> Runtime {
-        Runtime { env }
+        Runtime {
+            env,
+            interrupt: Interrupt::new(),
+        }
     }
 
-    pub fn rep_iter(&mut self, expr: SExp) -> Option<String> {
-        match self.eval(expr.into()) {
-            Ok(expr) => Some(format!("{:?}", expr)),
-            Err(error) => Some(format!("{:?}", error)),
-        }
+    /// List the global bindings, one per line, sorted by name.
+    pub fn describe_env(&self) -> String {
+        let mut bindings: Vec<(&Symbol, &Value)> = (self.env.0).iter().collect();
+        bindings.sort_by(|a, b| (a.0).0.cmp(&(b.0).0));
+        bindings
+            .into_iter()
+            .map(|(name, value)| format!("{} = {}", name.0, value))
+            .collect::<Vec<String>>()
+            .join("\n")
     }
 
     pub fn eval(&mut self, expr: Value) -> Result<Value, Error> {
+        if self.interrupt.is_triggered() {
+            return Err(Error::Interrupted);
+        }
         if expr.is_atom() {
             match expr {
                 Value::Symbol(name) => self.env.lookup(&name).ok_or(Error::UndefinedSymbol),
@@ -100,7 +117,7 @@ impl Runtime {
                                 .ok_or(Error::InvokeError)?;
                             let args = self.evlist(args)?;
 
-                            f.invoke(args)
+                            f.invoke(args, &self.interrupt)
                         }
                     }
                 } else {
@@ -113,7 +130,7 @@ impl Runtime {
                         .ok_or(Error::InvokeError)?;
                     let args = self.evlist(args)?;
 
-                    f.invoke(args)
+                    f.invoke(args, &self.interrupt)
                 }
             } else {
                 unreachable!()
@@ -161,6 +178,95 @@ impl Runtime {
     }
 }
 
+const SPECIAL_FORMS: &[(&str, &str)] = &[
+    (
+        "begin",
+        "(begin exprs...): evaluate each expression, producing the last",
+    ),
+    (
+        "if",
+        "(if condition consequent alternate): evaluate one branch",
+    ),
+    ("lambda", "(lambda (args...) body...): make a function"),
+    ("quote", "(quote expr): produce expr without evaluating it"),
+    ("set!", "(set! name expr): bind name to the value of expr"),
+];
+
+impl Language for Runtime {
+    type Value = Value;
+    type Error = Error;
+
+    fn name(&self) -> &str {
+        "L.I.S.P."
+    }
+
+    fn eval(&mut self, expr: SExp) -> Result<Option<Value>, Error> {
+        Runtime::eval(self, expr.into()).map(Some)
+    }
+
+    fn reset(&mut self) {
+        self.env = Env(HashMap::new());
+    }
+
+    fn set_interrupt(&mut self, interrupt: Interrupt) {
+        self.interrupt = interrupt;
+    }
+
+    fn save(&self) -> Vec<SExp> {
+        let mut bindings: Vec<(&Symbol, &Value)> = (self.env.0).iter().collect();
+        // functions go last so they close over the restored values
+        bindings.sort_by_key(|(name, value)| (value.is_fn(), &name.0));
+        bindings
+            .into_iter()
+            .filter_map(|(name, value)| {
+                let expr = match value {
+                    Value::LispFn(f) => f.to_sexp()?,
+                    value => SExp::List(vec![SExp::symbol("quote"), value.to_sexp()?]),
+                };
+                Some(SExp::List(vec![
+                    SExp::symbol("set!"),
+                    SExp::symbol(name.0.as_str()),
+                    expr,
+                ]))
+            })
+            .collect()
+    }
+
+    fn complete(&self, prefix: &str) -> Vec<String> {
+        let mut names: Vec<String> = SPECIAL_FORMS
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .chain((self.env.0).keys().map(|name| name.0.clone()))
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    fn describe(&self, name: &str) -> Option<String> {
+        SPECIAL_FORMS
+            .iter()
+            .find(|(form, _)| *form == name)
+            .map(|(_, doc)| doc.to_string())
+            .or_else(|| {
+                self.env
+                    .lookup(&Symbol(name.into()))
+                    .map(|value| format!("{} = {}", name, value))
+            })
+    }
+
+    fn defines(&self, form: &SExp) -> Option<String> {
+        match form {
+            SExp::List(exprs) => match exprs.as_slice() {
+                [SExp::Symbol(set), SExp::Symbol(name), ..] if set == "set!" => Some(name.clone()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -263,4 +369,94 @@ mod test {
             ))
         );
     }
+
+    #[test]
+    fn test_describe_env() {
+        let mut rt = Runtime::new();
+        rt.eval(lisp!("(set! b 2)")).unwrap();
+        rt.eval(lisp!("(set! a (lambda (x) x))")).unwrap();
+        assert_eq!("a = #<function>\nb = 2", rt.describe_env());
+    }
+
+    #[test]
+    fn test_display_value() {
+        let mut rt = Runtime::new();
+        let value = rt.eval(lisp!("(quote (1 \"two\" (3.5 true)))")).unwrap();
+        assert_eq!("(1 \"two\" (3.5 true))", value.to_string());
+    }
+
+    #[test]
+    fn test_complete() {
+        let mut rt = Runtime::new();
+        rt.eval(lisp!("(set! square 2)")).unwrap();
+        assert_eq!(vec!["set!", "square"], rt.complete("s"));
+    }
+
+    #[test]
+    fn test_describe() {
+        let mut rt = Runtime::new();
+        rt.eval(lisp!("(set! a 1)")).unwrap();
+        assert_eq!(Some("a = 1".to_string()), rt.describe("a"));
+        assert!(rt.describe("lambda").is_some());
+        assert_eq!(None, rt.describe("b"));
+    }
+
+    #[test]
+    fn test_defines() {
+        let rt = Runtime::new();
+        let form = |text| dcpl::SExpParser::parse_line(text).unwrap();
+        assert_eq!(Some("a".to_string()), rt.defines(&form("(set! a 1)")));
+        assert_eq!(None, rt.defines(&form("(quote a)")));
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut rt = Runtime::new();
+        rt.eval(lisp!("(set! a 1)")).unwrap();
+        Language::reset(&mut rt);
+        assert_eq!(Err(Error::UndefinedSymbol), rt.eval(lisp!("a")));
+    }
+
+    #[test]
+    fn test_interrupted() {
+        let mut rt = Runtime::new();
+        let interrupt = Interrupt::new();
+        rt.set_interrupt(interrupt.clone());
+        rt.eval(lisp!("(set! id (lambda (x) x))")).unwrap();
+        interrupt.trigger();
+        assert_eq!(Err(Error::Interrupted), rt.eval(lisp!("(id 1)")));
+    }
+
+    #[test]
+    fn test_save_and_restore() {
+        let mut rt = Runtime::new();
+        rt.eval(lisp!("(set! twice (lambda (x) (add x x)))"))
+            .unwrap();
+        rt.eval(lisp!("(set! data (quote (1 \"two\" true)))"))
+            .unwrap();
+        let image = rt.save();
+        let texts: Vec<String> = image.iter().map(SExp::to_string).collect();
+        assert_eq!(
+            vec![
+                "(set! data (quote (1 \"two\" true)))",
+                "(set! twice (lambda (x) (add x x)))"
+            ],
+            texts
+        );
+
+        let mut restored = Runtime::new();
+        for form in image {
+            Language::eval(&mut restored, form).unwrap();
+        }
+        assert_eq!(rt.describe_env(), restored.describe_env());
+        assert_eq!(rt.eval(lisp!("data")), restored.eval(lisp!("data")));
+    }
+
+    #[test]
+    fn test_transcripts() {
+        dcpl::transcript::assert_transcripts(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/transcripts"),
+            Runtime::new,
+        );
+    }
 }
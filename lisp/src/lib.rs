@@ -1,18 +1,37 @@
 use std::collections::HashMap;
+use std::fmt;
 
-use dcpl::SExp;
+use dcpl::registry::{AnyLanguage, Registration};
+use dcpl::{Interrupt, Language, MetaCommand, SExp};
 
 mod interpreter;
 pub use crate::interpreter::Runtime;
 
 mod list;
 
+/// The `:env` meta-command.
+pub fn env_command() -> MetaCommand<Runtime> {
+    MetaCommand::new(
+        "env",
+        "show the global bindings",
+        |runtime: &mut Runtime, _| Some(runtime.describe_env()),
+    )
+}
+
+/// Lisp, for the `dcpl` launcher.
+pub fn registration() -> Registration {
+    Registration::new("lisp", &["lisp", "lsp"], || {
+        AnyLanguage::with_commands(Runtime::new(), vec![env_command()])
+    })
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Error {
     BeginError,
     EPrognError,
     EvListError,
     IfError,
+    Interrupted,
     InvokeError,
     LambdaError,
     NotAFunction,
@@ -37,7 +56,7 @@ impl Env {
     fn extend(&self, names: &List, values: List) -> Env {
         let mut new_env = self.clone();
 
-        let pairs = names.into_iter().zip(values.into_iter());
+        let pairs = names.into_iter().zip(values);
         for (name, value) in pairs {
             let name_sym = name
                 .clone()
@@ -94,9 +113,23 @@ pub struct LispFn {
 }
 
 impl LispFn {
-    pub fn invoke(&self, arguments: List) -> Result<Value, Error> {
+    /// The `lambda` form that makes this function again. Whatever the
+    /// function closed over is left out.
+    pub fn to_sexp(&self) -> Option<SExp> {
+        let mut form = vec![
+            SExp::symbol("lambda"),
+            Value::List(self.arg_names.clone()).to_sexp()?,
+        ];
+        for expr in &self.body {
+            form.push(expr.to_sexp()?);
+        }
+        Some(SExp::List(form))
+    }
+
+    pub fn invoke(&self, arguments: List, interrupt: &Interrupt) -> Result<Value, Error> {
         let fn_env = self.env.extend(&self.arg_names, arguments);
         let mut rt = Runtime::new_with_env(fn_env);
+        rt.set_interrupt(interrupt.clone());
         let mut last = Value::List(List::Nil);
         for value in self.body.clone() {
             last = rt.eval(value)?;
@@ -235,16 +268,50 @@ impl Value {
     }
 
     pub fn is_atom(&self) -> bool {
+        !matches!(self, Value::List(_))
+    }
+
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Bool(Bool(false)) | Value::List(List::Nil))
+    }
+
+    /// The s-expression that reads back as this value, or `None` for
+    /// functions and lists holding them.
+    pub fn to_sexp(&self) -> Option<SExp> {
         match self {
-            Value::List(_) => false,
-            _ => true,
+            Value::List(list) => list
+                .into_iter()
+                .map(Value::to_sexp)
+                .collect::<Option<Vec<SExp>>>()
+                .map(SExp::List),
+            Value::Symbol(Symbol(name)) => Some(SExp::symbol(name.as_str())),
+            Value::String(content) => Some(SExp::string(content.as_str())),
+            Value::Integer(Integer(value)) => Some(SExp::Integer(*value)),
+            Value::Double(Double(value)) => Some(SExp::Float(*value)),
+            Value::Bool(Bool(value)) => Some(SExp::symbol(value.to_string())),
+            Value::LispFn(_) => None,
         }
     }
+}
 
-    pub fn is_truthy(&self) -> bool {
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match self {
-            Value::Bool(Bool(false)) | Value::List(List::Nil) => false,
-            _ => true,
+            Value::List(list) => {
+                write!(f, "(")?;
+                let mut sep = "";
+                for value in list {
+                    write!(f, "{}{}", sep, value)?;
+                    sep = " ";
+                }
+                write!(f, ")")
+            }
+            Value::Symbol(Symbol(name)) => write!(f, "{}", name),
+            Value::String(content) => write!(f, "\"{}\"", content),
+            Value::Integer(Integer(value)) => write!(f, "{}", value),
+            Value::Double(Double(value)) => write!(f, "{}", value),
+            Value::Bool(Bool(value)) => write!(f, "{}", value),
+            Value::LispFn(_) => write!(f, "#<function>"),
         }
     }
 }
@@ -257,6 +324,11 @@ impl From<SExp> for Value {
             SExp::Float(value) => Value::Double(Double(value)),
             SExp::String(value) => Value::String(value),
             SExp::Symbol(name) => Symbol(name).read(),
+            // `SExp::BigInteger`, when dcpl is built with `bignum` for
+            // another language. Lisp integers are `i128`s, so the
+            // nearest we have is a double.
+            #[allow(unreachable_patterns)]
+            big => Value::Double(Double(big.to_string().parse().unwrap_or(f64::NAN))),
         }
     }
 }
@@ -1,9 +1,14 @@
-use dcpl::Interpreter;
+use std::env;
+use std::process;
 
-use lisp::Runtime;
+use dcpl::cli;
+
+use lisp::{env_command, Runtime};
 
 fn main() {
-    let mut runtime = Runtime::new();
-    let mut interpreter = Interpreter::new("L.I.S.P.", move |expr| runtime.rep_iter(expr));
-    interpreter.run();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let code = cli::run(&args, Runtime::new, |interpreter| {
+        interpreter.add_command(env_command())
+    });
+    process::exit(code);
 }
@@ -0,0 +1,41 @@
+//! Read and run arbitrary postfix programs. The first form of the input
+//! is the list of arguments and the rest are the program's commands, so
+//! `(3 4) 1 nget mul` runs `(def fuzz 2 1 nget mul)` then `(fuzz 3 4)`.
+//! Every input should give a value or an error, never a panic.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use dcpl::{Language, SExp, SExpParser};
+
+use postfix::TopLevel;
+
+fuzz_target!(|data: &[u8]| {
+    let text = match std::str::from_utf8(data) {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+    let mut forms = match SExpParser::parse_file(text) {
+        Ok(forms) => forms.into_iter(),
+        Err(_) => return,
+    };
+    let args = match forms.next() {
+        Some(SExp::List(args)) => args,
+        _ => return,
+    };
+
+    let mut def = vec![
+        SExp::symbol("def"),
+        SExp::symbol("fuzz"),
+        SExp::Integer(args.len() as i128),
+    ];
+    def.extend(forms);
+    let mut call = vec![SExp::symbol("fuzz")];
+    call.extend(args);
+
+    let mut top_level = TopLevel::new();
+    if top_level.eval(SExp::List(def)).is_ok() {
+        let _ = top_level.eval(SExp::List(call));
+    }
+});
@@ -0,0 +1,450 @@
+//! A static check of postfix programs. It follows the stack a program
+//! builds, from its arguments on, without running it: how deep the
+//! stack is and what kind of value is in each slot. Failures that
+//! would happen on every run, like popping an empty stack or adding a
+//! sequence, are found before the program is called.
+//!
+//! Sequences pushed by the program are known, so `exec` of one is
+//! followed into it. When the sequence can't be known, as when `sel`
+//! picks between two on a computed condition, nothing is known about
+//! the stack after the `exec`.
+
+use std::fmt;
+
+use crate::integer::{self, Integer};
+use crate::program::{Error, Program};
+use crate::read::{BuiltIn, Command};
+use crate::top_level::Error as TopLevelError;
+use crate::trace;
+
+/// What is known about a value on the stack: its kind and, when it is
+/// the same on every run, the value itself.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Slot {
+    Integer(Option<Integer>),
+    ExecutableSequence(Option<Vec<Command>>),
+    Unknown,
+}
+
+impl Slot {
+    /// Both `self` and `other`, for a value that may be either.
+    fn join(self, other: Slot) -> Slot {
+        match (self, other) {
+            (slot, other) if slot == other => slot,
+            (Slot::Integer(_), Slot::Integer(_)) => Slot::Integer(None),
+            (Slot::ExecutableSequence(_), Slot::ExecutableSequence(_)) => {
+                Slot::ExecutableSequence(None)
+            }
+            _ => Slot::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Slot::Integer(Some(value)) => write!(f, "{}", value),
+            Slot::Integer(None) => write!(f, "int"),
+            Slot::ExecutableSequence(Some(commands)) => {
+                write!(f, "{}", Command::ExecutableSequence(commands.clone()))
+            }
+            Slot::ExecutableSequence(None) => write!(f, "seq"),
+            Slot::Unknown => write!(f, "?"),
+        }
+    }
+}
+
+/// What is known about the whole stack.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Shape {
+    /// The values at the top of the stack, bottom first.
+    pub slots: Vec<Slot>,
+    /// Whether there may be more values below `slots`, after an `exec`
+    /// of an unknown sequence.
+    pub open: bool,
+}
+
+impl Shape {
+    /// How many values are on the stack or, if it is open, the fewest
+    /// there may be.
+    pub fn depth(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn unknown() -> Shape {
+        Shape {
+            slots: vec![],
+            open: true,
+        }
+    }
+
+    fn pop(&mut self) -> Result<Slot, Error> {
+        match self.slots.pop() {
+            Some(slot) => Ok(slot),
+            None if self.open => Ok(Slot::Unknown),
+            None => Err(Error::NotEnoughValues),
+        }
+    }
+
+    /// Pop a value that must be an integer, and give what is known of it.
+    fn pop_integer(&mut self) -> Result<Option<Integer>, Error> {
+        match self.pop()? {
+            Slot::Integer(value) => Ok(value),
+            Slot::ExecutableSequence(_) => Err(Error::NotANumber),
+            Slot::Unknown => Ok(None),
+        }
+    }
+
+    fn push(&mut self, slot: Slot) {
+        self.slots.push(slot);
+    }
+}
+
+/// Written top first, like a stack in a trace, with `...` for the
+/// unknown values below an open stack.
+impl fmt::Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let mut slots: Vec<String> = self.slots.iter().rev().map(ToString::to_string).collect();
+        if self.open {
+            slots.push("...".into());
+        }
+        write!(f, "[{}]", slots.join(", "))
+    }
+}
+
+/// One command checked, in the order the program would run it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Point {
+    pub command: Command,
+    /// How many `exec`s the command runs inside of.
+    pub depth: usize,
+    /// What is known of the stack after the command, or the error it
+    /// fails with on every run. For `exec` of a known sequence this is
+    /// after the whole sequence.
+    pub after: Result<Shape, Error>,
+}
+
+/// Everything the check found about a program.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Analysis {
+    /// The stack the program starts with: its arguments.
+    pub start: Shape,
+    pub points: Vec<Point>,
+    /// What is known of the program's result, or the error it fails
+    /// with on every run.
+    pub end: Result<Slot, TopLevelError>,
+}
+
+impl Analysis {
+    /// The command the program always fails at and its error, if it
+    /// always fails. The command is `None` if the program runs to the
+    /// end but leaves no integer on top of the stack.
+    pub fn failure(&self) -> Option<(Option<&Command>, &TopLevelError)> {
+        let error = self.end.as_ref().err()?;
+        let command = self
+            .points
+            .last()
+            .filter(|point| point.after.is_err())
+            .map(|point| &point.command);
+        Some((command, error))
+    }
+}
+
+/// A table like a trace's, of what is known of the stack after each
+/// command, then the result.
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let mut rows = vec![
+            ("Command".to_string(), "Stack".to_string()),
+            (String::new(), self.start.to_string()),
+        ];
+        for point in &self.points {
+            let command = format!("{}{}", "  ".repeat(point.depth), point.command);
+            let after = match &point.after {
+                Ok(after) => after.to_string(),
+                Err(error) => format!("error: {:?}", error),
+            };
+            rows.push((command, after));
+        }
+        write!(f, "{}", trace::layout(rows))?;
+        match &self.end {
+            Ok(slot) => write!(f, "result: {}", slot),
+            Err(error) => write!(f, "error: {:?}", error),
+        }
+    }
+}
+
+/// Check `program`, with nothing known about its arguments but that
+/// they are integers.
+pub fn check(program: &Program) -> Analysis {
+    let start = Shape {
+        slots: vec![Slot::Integer(None); program.num_args()],
+        open: false,
+    };
+    let mut checker = Checker {
+        shape: start.clone(),
+        points: vec![],
+    };
+    let end = checker
+        .run(program.commands(), 0)
+        .map_err(TopLevelError::from)
+        .and_then(|()| match checker.shape.pop()? {
+            Slot::ExecutableSequence(_) => Err(TopLevelError::FinalValueNotAnInteger),
+            slot => Ok(slot),
+        });
+    Analysis {
+        start,
+        points: checker.points,
+        end,
+    }
+}
+
+struct Checker {
+    shape: Shape,
+    points: Vec<Point>,
+}
+
+impl Checker {
+    fn run(&mut self, commands: &[Command], depth: usize) -> Result<(), Error> {
+        for command in commands {
+            let point = self.points.len();
+            self.points.push(Point {
+                command: command.clone(),
+                depth,
+                after: Ok(Shape::default()),
+            });
+            let result = self.apply(command, depth);
+            self.points[point].after = match &result {
+                Ok(()) => Ok(self.shape.clone()),
+                Err(error) => Err(error.clone()),
+            };
+            result?;
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, command: &Command, depth: usize) -> Result<(), Error> {
+        match command {
+            Command::Integer(value) => self.shape.push(Slot::Integer(Some(value.clone()))),
+            Command::ExecutableSequence(commands) => self
+                .shape
+                .push(Slot::ExecutableSequence(Some(commands.clone()))),
+            Command::BuiltIn(builtin) => return self.apply_builtin(builtin, depth),
+        }
+        Ok(())
+    }
+
+    fn apply_builtin(&mut self, builtin: &BuiltIn, depth: usize) -> Result<(), Error> {
+        use crate::read::BuiltIn::*;
+        let shape = &mut self.shape;
+        match builtin {
+            Add | Sub | Mul | Eq | Gt | Lt => {
+                shape.pop_integer()?;
+                shape.pop_integer()?;
+                shape.push(Slot::Integer(None));
+            }
+            Div | Rem => {
+                let divisor = shape.pop_integer()?;
+                shape.pop_integer()?;
+                if divisor.as_ref().is_some_and(integer::is_zero) {
+                    return Err(Error::DivideByZero);
+                }
+                shape.push(Slot::Integer(None));
+            }
+            Pop => {
+                shape.pop()?;
+            }
+            Swap => {
+                let v1 = shape.pop()?;
+                let v2 = shape.pop()?;
+                shape.push(v1);
+                shape.push(v2);
+            }
+            Sel => {
+                let v1 = shape.pop()?;
+                let v2 = shape.pop()?;
+                let v3 = match shape.pop()? {
+                    Slot::ExecutableSequence(_) => return Err(Error::NotANumber),
+                    Slot::Integer(value) => value,
+                    Slot::Unknown => None,
+                };
+                shape.push(match v3 {
+                    Some(v3) if integer::is_zero(&v3) => v1,
+                    Some(_) => v2,
+                    None => v2.join(v1),
+                });
+            }
+            Nget => {
+                let slot = match shape.pop_integer()? {
+                    Some(vindex) => Checker::nget(shape, vindex)?,
+                    None => Slot::Integer(None),
+                };
+                shape.push(slot);
+            }
+            Exec => match shape.pop()? {
+                Slot::Integer(_) => return Err(Error::NotAnExecutableSequence),
+                Slot::ExecutableSequence(Some(commands)) => return self.run(&commands, depth + 1),
+                Slot::ExecutableSequence(None) | Slot::Unknown => *shape = Shape::unknown(),
+            },
+        }
+        Ok(())
+    }
+
+    /// The value `nget` copies from `vindex` values down in `shape`.
+    fn nget(shape: &Shape, vindex: Integer) -> Result<Slot, Error> {
+        let len = shape.slots.len();
+        match integer::to_index(&vindex).filter(|&index| index >= 1) {
+            Some(index) if index <= len => match &shape.slots[len - index] {
+                Slot::ExecutableSequence(_) => Err(Error::NotANumber),
+                Slot::Integer(value) => Ok(Slot::Integer(value.clone())),
+                Slot::Unknown => Ok(Slot::Integer(None)),
+            },
+            Some(_) if shape.open => Ok(Slot::Integer(None)),
+            _ => Err(Error::IndexOutOfRange(vindex)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use dcpl::Interrupt;
+
+    use crate::integer::int;
+
+    fn program(num_args: usize, text: &str) -> Program {
+        let sexp = dcpl::SExpParser::parse_line(text).unwrap();
+        match Command::read(sexp).unwrap() {
+            Command::ExecutableSequence(commands) => Program::new(num_args, commands),
+            _ => panic!("expected a sequence"),
+        }
+    }
+
+    fn end(num_args: usize, text: &str) -> Result<Slot, TopLevelError> {
+        check(&program(num_args, text)).end
+    }
+
+    #[test]
+    fn test_depth_and_kinds() {
+        let analysis = check(&program(1, "(((2 mul) exec) 3 swap)"));
+        let shapes: Vec<String> = analysis
+            .points
+            .iter()
+            .map(|point| point.after.as_ref().unwrap().to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                "[((2 mul) exec), int]",
+                "[3, ((2 mul) exec), int]",
+                "[((2 mul) exec), 3, int]"
+            ],
+            shapes
+        );
+        assert_eq!(3, analysis.points[2].after.as_ref().unwrap().depth());
+        assert_eq!(Err(TopLevelError::FinalValueNotAnInteger), analysis.end);
+    }
+
+    #[test]
+    fn test_definite_errors() {
+        let not_enough = Err(Error::NotEnoughValues.into());
+        assert_eq!(not_enough, end(0, "(1 swap)"));
+        assert_eq!(not_enough, end(2, "(add add)"));
+        assert_eq!(not_enough, end(0, "(1 pop)"));
+        assert_eq!(Err(Error::NotANumber.into()), end(1, "((2) add)"));
+        assert_eq!(Err(Error::NotANumber.into()), end(0, "((2 mul) 1 nget)"));
+        assert_eq!(
+            Err(Error::NotAnExecutableSequence.into()),
+            end(0, "(1 exec)")
+        );
+        assert_eq!(Err(Error::DivideByZero.into()), end(1, "(0 div)"));
+        assert_eq!(
+            Err(Error::IndexOutOfRange(int(3)).into()),
+            end(2, "(3 nget)")
+        );
+    }
+
+    #[test]
+    fn test_possible_errors_are_not_reported() {
+        // The divisor and the index are only known when it runs.
+        assert_eq!(Ok(Slot::Integer(None)), end(2, "(div)"));
+        assert_eq!(Ok(Slot::Integer(None)), end(3, "(nget)"));
+    }
+
+    #[test]
+    fn test_exec_of_known_sequence() {
+        let analysis = check(&program(1, "(((2 mul) exec) exec)"));
+        assert_eq!(Ok(Slot::Integer(None)), analysis.end);
+        let depths: Vec<usize> = analysis.points.iter().map(|point| point.depth).collect();
+        assert_eq!(vec![0, 0, 1, 1, 2, 2], depths);
+        assert_eq!(Err(Error::NotEnoughValues.into()), end(0, "(1 (add) exec)"));
+    }
+
+    #[test]
+    fn test_sel() {
+        // A known condition picks one value; otherwise either may be picked.
+        assert_eq!(Ok(Slot::Integer(Some(int(3)))), end(0, "(0 2 3 sel)"));
+        assert_eq!(Ok(Slot::Integer(None)), end(1, "(2 3 sel)"));
+        assert_eq!(Ok(Slot::Unknown), end(1, "((2) 3 sel)"));
+        assert_eq!(Err(Error::NotANumber.into()), end(0, "((1) 2 3 sel)"));
+    }
+
+    #[test]
+    fn test_unknown_after_dynamic_exec() {
+        let analysis = check(&program(2, "(lt (add) (mul) sel exec)"));
+        let after_exec = analysis.points.last().unwrap().after.clone();
+        assert_eq!(Ok(Shape::unknown()), after_exec);
+        assert_eq!("[...]", after_exec.unwrap().to_string());
+        assert_eq!(Ok(Slot::Unknown), analysis.end);
+        // Nothing is known to be missing from an open stack.
+        assert_eq!(
+            Ok(Slot::Integer(None)),
+            end(2, "(lt (add) (mul) sel exec 5 nget)")
+        );
+    }
+
+    #[test]
+    fn test_failure() {
+        let analysis = check(&program(0, "(1 2 swap 3 exec)"));
+        assert_eq!(
+            Some((
+                Some(&Command::BuiltIn(BuiltIn::Exec)),
+                &Error::NotAnExecutableSequence.into()
+            )),
+            analysis.failure()
+        );
+        let analysis = check(&program(0, "((1))"));
+        assert_eq!(
+            Some((None, &TopLevelError::FinalValueNotAnInteger)),
+            analysis.failure()
+        );
+        assert_eq!(None, check(&program(1, "(1 add)")).failure());
+    }
+
+    #[test]
+    fn test_definite_errors_happen() {
+        let interrupt = Interrupt::new();
+        for (num_args, text) in &[(0, "(1 swap)"), (1, "((2) add)"), (2, "(1 (4 nget) exec)")] {
+            let program = program(*num_args, text);
+            let args = vec![int(1); *num_args];
+            assert_eq!(
+                check(&program).end.map(|_| ()),
+                program.apply(args, &interrupt).map(|_| ())
+            );
+        }
+    }
+
+    #[test]
+    fn test_table() {
+        assert_eq!(
+            "Command  Stack\n\
+             \x20        [int]\n\
+             (2 mul)  [(2 mul), int]\n\
+             exec     [int]\n\
+             \x20 2      [2, int]\n\
+             \x20 mul    [int]\n\
+             result: int",
+            check(&program(1, "((2 mul) exec)")).to_string()
+        );
+    }
+}
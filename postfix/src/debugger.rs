@@ -0,0 +1,289 @@
+//! A debugger for postfix programs: it runs a program on a machine a
+//! command at a time, stopping after each step or at breakpoints, so
+//! the stack can be looked at and changed along the way.
+
+use std::fmt;
+
+use dcpl::SExp;
+
+use crate::integer::Integer;
+use crate::machine::Machine;
+use crate::program::StackValue;
+use crate::read::{BuiltIn, Command};
+use crate::top_level::Error as TopLevelError;
+use crate::trace;
+
+/// Where a program being debugged stops.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Breakpoint {
+    /// Before the command at this index in the program itself, not in
+    /// a sequence it `exec`s.
+    Index(usize),
+    /// Before every use of the builtin.
+    BuiltIn(BuiltIn),
+}
+
+impl Breakpoint {
+    fn matches(&self, machine: &Machine) -> bool {
+        match self {
+            Breakpoint::Index(index) => machine.depth() == 0 && machine.index() == *index,
+            Breakpoint::BuiltIn(builtin) => {
+                machine.next_command() == Some(&Command::BuiltIn(builtin.clone()))
+            }
+        }
+    }
+
+    pub fn to_sexp(&self) -> SExp {
+        match self {
+            Breakpoint::Index(index) => SExp::Integer(*index as i128),
+            Breakpoint::BuiltIn(builtin) => SExp::symbol(builtin.name()),
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Breakpoint::Index(index) => write!(f, "{}", index),
+            Breakpoint::BuiltIn(builtin) => write!(f, "{}", builtin),
+        }
+    }
+}
+
+/// How far to run a program being debugged. Each runs at least one
+/// command, and all but `Into` stop early at a breakpoint.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Step {
+    /// Run one command; after `exec`, stop at the first command of its
+    /// sequence.
+    Into,
+    /// Run one command, including the whole sequence of an `exec`.
+    Over,
+    /// Run until the sequence being executed is finished.
+    Out,
+    /// Run until the program is finished.
+    Continue,
+}
+
+/// Where a paused program is, and its stack.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pause {
+    /// How many `exec`s the next command is nested in.
+    pub depth: usize,
+    /// Where the next command is in the sequence being executed.
+    pub index: usize,
+    /// The commands left in that sequence, starting with the next one.
+    pub remaining: Vec<Command>,
+    /// The stack, bottom first.
+    pub stack: Vec<StackValue>,
+}
+
+impl fmt::Display for Pause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let remaining: Vec<String> = self.remaining.iter().map(ToString::to_string).collect();
+        writeln!(
+            f,
+            "depth {}, command {}: {}",
+            self.depth,
+            self.index,
+            remaining.join(" ")
+        )?;
+        write!(f, "stack: {}", trace::stack(&self.stack))
+    }
+}
+
+/// The breakpoints, which stay set from one program to the next, and
+/// the program being debugged, if there is one.
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    machine: Option<Machine>,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger::default()
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    /// Remove `breakpoint`, returning whether it was set.
+    pub fn remove_breakpoint(&mut self, breakpoint: &Breakpoint) -> bool {
+        let len = self.breakpoints.len();
+        self.breakpoints.retain(|set| set != breakpoint);
+        self.breakpoints.len() != len
+    }
+
+    /// Start debugging the program `machine` is about to run, stopped
+    /// before its first command. If it has no commands to run, it is
+    /// finished at once and its result is returned instead.
+    pub fn start(&mut self, machine: Machine) -> Option<Result<Integer, TopLevelError>> {
+        if machine.is_finished() {
+            return Some(machine.result());
+        }
+        self.machine = Some(machine);
+        None
+    }
+
+    pub fn is_debugging(&self) -> bool {
+        self.machine.is_some()
+    }
+
+    /// Stop debugging, abandoning the program.
+    pub fn quit(&mut self) {
+        self.machine = None;
+    }
+
+    pub fn machine(&self) -> Option<&Machine> {
+        self.machine.as_ref()
+    }
+
+    pub fn machine_mut(&mut self) -> Option<&mut Machine> {
+        self.machine.as_mut()
+    }
+
+    /// Where the program being debugged is paused.
+    pub fn pause(&self) -> Option<Pause> {
+        self.machine.as_ref().map(|machine| Pause {
+            depth: machine.depth(),
+            index: machine.index(),
+            remaining: machine.remaining().to_vec(),
+            stack: machine.stack().to_vec(),
+        })
+    }
+
+    /// Run the program being debugged as far as `step` says. Once it
+    /// finishes or fails, debugging stops and its result is returned;
+    /// otherwise it is paused again.
+    pub fn resume(&mut self, step: Step) -> Result<Option<Integer>, TopLevelError> {
+        let machine = self.machine.as_mut().ok_or(TopLevelError::NotDebugging)?;
+        let depth = machine.depth();
+        loop {
+            if let Err(error) = machine.step() {
+                self.machine = None;
+                return Err(error.into());
+            }
+            if machine.is_finished() {
+                let machine = self.machine.take().unwrap();
+                return machine.result().map(Some);
+            }
+            let stop = match step {
+                Step::Into => true,
+                Step::Over => machine.depth() <= depth,
+                Step::Out => machine.depth() < depth,
+                Step::Continue => false,
+            };
+            if stop || self.breakpoints.iter().any(|b| b.matches(machine)) {
+                return Ok(None);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use dcpl::Interrupt;
+
+    use crate::integer::int;
+    use crate::program::{Limits, Program};
+
+    fn debugger(text: &str, args: Vec<i128>) -> Debugger {
+        let sexp = dcpl::SExpParser::parse_line(text).unwrap();
+        let commands = match Command::read(sexp).unwrap() {
+            Command::ExecutableSequence(commands) => commands,
+            _ => panic!("expected a sequence"),
+        };
+        let program = Program::new(args.len(), commands);
+        let args = args.into_iter().map(int).collect();
+        let machine = Machine::new(&program, args, Interrupt::new(), Limits::default()).unwrap();
+        let mut debugger = Debugger::new();
+        assert_eq!(None, debugger.start(machine));
+        debugger
+    }
+
+    fn position(debugger: &Debugger) -> (usize, usize) {
+        let pause = debugger.pause().unwrap();
+        (pause.depth, pause.index)
+    }
+
+    #[test]
+    fn test_step_into_and_out() {
+        let mut debugger = debugger("((2 mul) exec 1 add)", vec![5]);
+        assert_eq!((0, 0), position(&debugger));
+        assert_eq!(Ok(None), debugger.resume(Step::Into));
+        assert_eq!(Ok(None), debugger.resume(Step::Into));
+        assert_eq!((1, 0), position(&debugger));
+        assert_eq!(Ok(None), debugger.resume(Step::Out));
+        assert_eq!((0, 2), position(&debugger));
+        assert_eq!(vec![StackValue::from(10)], debugger.pause().unwrap().stack);
+        assert_eq!(Ok(Some(int(11))), debugger.resume(Step::Continue));
+        assert!(!debugger.is_debugging());
+    }
+
+    #[test]
+    fn test_step_over_exec() {
+        let mut debugger = debugger("((2 mul) exec 1 add)", vec![5]);
+        debugger.resume(Step::Over).unwrap();
+        debugger.resume(Step::Over).unwrap();
+        assert_eq!((0, 2), position(&debugger));
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut debugger = debugger("((2 mul) exec 1 add)", vec![5]);
+        debugger.add_breakpoint(Breakpoint::BuiltIn(BuiltIn::Mul));
+        debugger.add_breakpoint(Breakpoint::Index(3));
+        debugger.add_breakpoint(Breakpoint::Index(3));
+        assert_eq!(2, debugger.breakpoints().len());
+        // Stepping over the `exec` still stops at the `mul` inside it.
+        debugger.resume(Step::Over).unwrap();
+        debugger.resume(Step::Over).unwrap();
+        assert_eq!((1, 1), position(&debugger));
+        debugger.resume(Step::Continue).unwrap();
+        assert_eq!((0, 3), position(&debugger));
+        assert!(debugger.remove_breakpoint(&Breakpoint::Index(3)));
+        assert!(!debugger.remove_breakpoint(&Breakpoint::Index(3)));
+    }
+
+    #[test]
+    fn test_modify_stack() {
+        let mut debugger = debugger("(1 add)", vec![5]);
+        debugger.machine_mut().unwrap().stack_mut()[0] = StackValue::from(41);
+        assert_eq!(Ok(Some(int(42))), debugger.resume(Step::Continue));
+    }
+
+    #[test]
+    fn test_error_stops_debugging() {
+        let mut debugger = debugger("(0 div)", vec![5]);
+        assert_eq!(
+            Err(TopLevelError::ProgramError(
+                crate::program::Error::DivideByZero
+            )),
+            debugger.resume(Step::Continue)
+        );
+        assert!(!debugger.is_debugging());
+        assert_eq!(
+            Err(TopLevelError::NotDebugging),
+            debugger.resume(Step::Into)
+        );
+    }
+
+    #[test]
+    fn test_pause_display() {
+        let debugger = debugger("((2 mul) exec)", vec![5]);
+        assert_eq!(
+            "depth 0, command 0: (2 mul) exec\nstack: [5]",
+            debugger.pause().unwrap().to_string()
+        );
+    }
+}
@@ -0,0 +1,265 @@
+//! Postfix's denotational semantics, from DCPL's valuation functions.
+//! A command means a function from stacks to stacks, a sequence of
+//! commands the composition of its commands' functions, and a program
+//! a function from arguments to an answer. An executable sequence on
+//! the stack is its meaning, so `exec` just applies it.
+//!
+//! As in the book, every failure is the same `error`: an error stack
+//! goes through every later command unchanged and ends as an error
+//! answer. This evaluator has no steps to count, so it can't be
+//! interrupted or limited.
+
+use std::fmt;
+use std::rc::Rc;
+
+use crate::integer::{self, Integer};
+use crate::program::Program;
+use crate::read::{BuiltIn, Command};
+
+/// What a program gives back: an integer, or `error`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Answer {
+    Integer(Integer),
+    Error,
+}
+
+impl fmt::Display for Answer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Answer::Integer(value) => write!(f, "{}", value),
+            Answer::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A function from stacks to stacks, the meaning of a command.
+pub type Transform = Rc<dyn Fn(Stack) -> Stack>;
+
+/// A value on the stack.
+#[derive(Clone)]
+pub enum Value {
+    Integer(Integer),
+    Transform(Transform),
+}
+
+/// The values, top first, or `error`.
+#[derive(Clone)]
+pub enum Stack {
+    Values(Vec<Value>),
+    Error,
+}
+
+impl Stack {
+    /// The top value and the rest of the stack, if there is one.
+    fn pop(self) -> Option<(Value, Vec<Value>)> {
+        match self {
+            Stack::Values(mut values) if !values.is_empty() => {
+                let top = values.remove(0);
+                Some((top, values))
+            }
+            _ => None,
+        }
+    }
+
+    fn push(value: Value, mut rest: Vec<Value>) -> Stack {
+        rest.insert(0, value);
+        Stack::Values(rest)
+    }
+}
+
+/// P: the meaning of a program, from its arguments to its answer.
+pub fn program(program: &Program, args: Vec<Integer>) -> Answer {
+    if args.len() != program.num_args() {
+        return Answer::Error;
+    }
+    let stack = Stack::Values(args.into_iter().map(Value::Integer).collect());
+    match sequence(program.commands())(stack).pop() {
+        Some((Value::Integer(value), _)) => Answer::Integer(value),
+        _ => Answer::Error,
+    }
+}
+
+/// Q: the meaning of a sequence of commands, each command's meaning
+/// applied after the one before it.
+pub fn sequence(commands: &[Command]) -> Transform {
+    commands
+        .iter()
+        .map(command)
+        .fold(Rc::new(|stack| stack) as Transform, |before, command| {
+            Rc::new(move |stack| command(before(stack)))
+        })
+}
+
+/// C: the meaning of a command.
+pub fn command(command: &Command) -> Transform {
+    match command {
+        Command::Integer(value) => {
+            let value = value.clone();
+            push(move || Value::Integer(value.clone()))
+        }
+        Command::ExecutableSequence(commands) => {
+            let meaning = sequence(commands);
+            push(move || Value::Transform(meaning.clone()))
+        }
+        Command::BuiltIn(builtin) => self::builtin(builtin),
+    }
+}
+
+fn push(value: impl Fn() -> Value + 'static) -> Transform {
+    Rc::new(move |stack| match stack {
+        Stack::Values(values) => Stack::push(value(), values),
+        Stack::Error => Stack::Error,
+    })
+}
+
+/// `transform`, but taking `error` to `error`.
+fn strict(transform: impl Fn(Stack) -> Stack + 'static) -> Transform {
+    Rc::new(move |stack| match stack {
+        Stack::Error => Stack::Error,
+        stack => transform(stack),
+    })
+}
+
+/// The meaning of an arithmetic or comparison builtin, given as
+/// `op(v2, v1)` for a stack `v1 . v2 . s`, or `None` for an error.
+fn binary(op: impl Fn(&Integer, &Integer) -> Option<Integer> + 'static) -> Transform {
+    strict(move |stack| match stack.pop() {
+        Some((Value::Integer(v1), rest)) => match Stack::Values(rest).pop() {
+            Some((Value::Integer(v2), rest)) => match op(&v2, &v1) {
+                Some(result) => Stack::push(Value::Integer(result), rest),
+                None => Stack::Error,
+            },
+            _ => Stack::Error,
+        },
+        _ => Stack::Error,
+    })
+}
+
+fn truth(holds: bool) -> Option<Integer> {
+    Some(Integer::from(if holds { 1 } else { 0 }))
+}
+
+fn builtin(builtin: &BuiltIn) -> Transform {
+    use crate::read::BuiltIn::*;
+    match builtin {
+        Add => binary(integer::add),
+        Sub => binary(integer::sub),
+        Mul => binary(integer::mul),
+        Div => binary(integer::div),
+        Rem => binary(integer::rem),
+        Eq => binary(|v2, v1| truth(v2 == v1)),
+        Gt => binary(|v2, v1| truth(v2 > v1)),
+        Lt => binary(|v2, v1| truth(v2 < v1)),
+        Pop => strict(|stack| match stack.pop() {
+            Some((_, rest)) => Stack::Values(rest),
+            None => Stack::Error,
+        }),
+        Swap => strict(|stack| match stack.pop() {
+            Some((v1, rest)) => match Stack::Values(rest).pop() {
+                Some((v2, mut rest)) => {
+                    rest.insert(0, v1);
+                    Stack::push(v2, rest)
+                }
+                None => Stack::Error,
+            },
+            None => Stack::Error,
+        }),
+        Sel => strict(|stack| {
+            let (v1, rest) = match stack.pop() {
+                Some(popped) => popped,
+                None => return Stack::Error,
+            };
+            let (v2, rest) = match Stack::Values(rest).pop() {
+                Some(popped) => popped,
+                None => return Stack::Error,
+            };
+            match Stack::Values(rest).pop() {
+                Some((Value::Integer(v3), rest)) if integer::is_zero(&v3) => Stack::push(v1, rest),
+                Some((Value::Integer(_), rest)) => Stack::push(v2, rest),
+                _ => Stack::Error,
+            }
+        }),
+        Nget => strict(|stack| match stack.pop() {
+            Some((Value::Integer(vindex), rest)) => {
+                let value = integer::to_index(&vindex)
+                    .filter(|&index| index >= 1)
+                    .and_then(|index| rest.get(index - 1));
+                match value {
+                    Some(Value::Integer(value)) => Stack::push(Value::Integer(value.clone()), rest),
+                    _ => Stack::Error,
+                }
+            }
+            _ => Stack::Error,
+        }),
+        Exec => strict(|stack| match stack.pop() {
+            Some((Value::Transform(meaning), rest)) => meaning(Stack::Values(rest)),
+            _ => Stack::Error,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use dcpl::Interrupt;
+    use proptest::prelude::*;
+
+    use crate::generate;
+    use crate::integer::int;
+    use crate::program::Limits;
+
+    fn denote(num_args: usize, text: &str, args: Vec<i128>) -> Answer {
+        let sexp = dcpl::SExpParser::parse_line(text).unwrap();
+        let commands = match Command::read(sexp).unwrap() {
+            Command::ExecutableSequence(commands) => commands,
+            _ => panic!("expected a sequence"),
+        };
+        let args = args.into_iter().map(int).collect();
+        Program::new(num_args, commands).denote(args)
+    }
+
+    #[test]
+    fn test_answers() {
+        let quadratic = "(4 nget 5 nget mul mul swap 4 nget mul add add)";
+        assert_eq!(
+            Answer::Integer(int(25)),
+            denote(4, quadratic, vec![3, 4, 5, 2])
+        );
+        assert_eq!(
+            Answer::Integer(int(11)),
+            denote(1, "((2 mul) exec 1 add)", vec![5])
+        );
+        let choose = "(lt (add) (mul) sel exec)";
+        assert_eq!(
+            Answer::Integer(int(12)),
+            denote(4, choose, vec![1, 2, 3, 4])
+        );
+        assert_eq!(Answer::Integer(int(7)), denote(4, choose, vec![2, 1, 3, 4]));
+        assert_eq!(Answer::Integer(int(-1)), denote(1, "(4 sub)", vec![3]));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Answer::Error, denote(0, "(1 swap)", vec![]));
+        assert_eq!(Answer::Error, denote(2, "(div)", vec![0, 1]));
+        assert_eq!(Answer::Error, denote(0, "((2 mul) 1 nget)", vec![]));
+        assert_eq!(Answer::Error, denote(0, "(1 exec)", vec![]));
+        assert_eq!(Answer::Error, denote(0, "((1))", vec![]));
+        assert_eq!(Answer::Error, denote(1, "(1)", vec![]));
+        // An error goes through everything after it.
+        assert_eq!(Answer::Error, denote(0, "(pop 1 2 add)", vec![]));
+        assert_eq!("error", Answer::Error.to_string());
+    }
+
+    proptest! {
+        #[test]
+        fn agrees_with_apply((program, args) in generate::program()) {
+            let expected = match program.apply_with_limits(args.clone(), &Interrupt::new(), Limits::unlimited()) {
+                Ok(value) => Answer::Integer(value),
+                Err(_) => Answer::Error,
+            };
+            prop_assert_eq!(expected, program.denote(args));
+        }
+    }
+}
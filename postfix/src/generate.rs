@@ -0,0 +1,29 @@
+//! Postfix programs for property tests to run.
+
+use proptest::prelude::*;
+
+use crate::integer::{int, Integer};
+use crate::program::Program;
+use crate::read::{BuiltIn, Command};
+
+/// Any command, with small integers so that `nget` and `sel` often
+/// find what they look for.
+pub fn command() -> impl Strategy<Value = Command> {
+    let leaf = prop_oneof![
+        (-2i128..6).prop_map(|value| Command::Integer(int(value))),
+        proptest::sample::select(BuiltIn::ALL).prop_map(Command::BuiltIn),
+    ];
+    leaf.prop_recursive(3, 24, 4, |inner| {
+        proptest::collection::vec(inner, 0..5).prop_map(Command::ExecutableSequence)
+    })
+}
+
+/// A program along with arguments to call it with.
+pub fn program() -> impl Strategy<Value = (Program, Vec<Integer>)> {
+    proptest::collection::vec(-3i128..10, 0..4).prop_flat_map(|args| {
+        let num_args = args.len();
+        let args = args.into_iter().map(int).collect::<Vec<Integer>>();
+        proptest::collection::vec(command(), 0..12)
+            .prop_map(move |commands| (Program::new(num_args, commands), args.clone()))
+    })
+}
@@ -0,0 +1,159 @@
+//! The integers postfix programs compute with. They are `i128`s, or
+//! integers of any size with the `bignum` feature. Either way the
+//! arithmetic here agrees on every result that fits in an `i128`; only
+//! `i128`s can overflow.
+
+use std::convert::TryFrom;
+
+use dcpl::SExp;
+
+#[cfg(not(feature = "bignum"))]
+pub type Integer = i128;
+
+#[cfg(feature = "bignum")]
+pub type Integer = dcpl::BigInt;
+
+/// The integer `sexp` stands for, or `sexp` back if it isn't one.
+pub fn from_sexp(sexp: SExp) -> Result<Integer, SExp> {
+    match sexp {
+        #[cfg(not(feature = "bignum"))]
+        SExp::Integer(value) => Ok(value),
+        #[cfg(feature = "bignum")]
+        SExp::Integer(value) => Ok(value.into()),
+        #[cfg(feature = "bignum")]
+        SExp::BigInteger(value) => Ok(value),
+        sexp => Err(sexp),
+    }
+}
+
+pub fn to_sexp(value: Integer) -> SExp {
+    #[cfg(feature = "bignum")]
+    {
+        SExp::big_integer(value)
+    }
+    #[cfg(not(feature = "bignum"))]
+    {
+        SExp::Integer(value)
+    }
+}
+
+pub fn is_zero(value: &Integer) -> bool {
+    *value == Integer::from(0)
+}
+
+/// `value` as a position on the stack, if it could be one.
+pub fn to_index(value: &Integer) -> Option<usize> {
+    #[cfg(feature = "bignum")]
+    {
+        usize::try_from(value).ok()
+    }
+    #[cfg(not(feature = "bignum"))]
+    {
+        usize::try_from(*value).ok()
+    }
+}
+
+#[cfg(not(feature = "bignum"))]
+mod ops {
+    use super::Integer;
+
+    pub fn add(v1: &Integer, v2: &Integer) -> Option<Integer> {
+        v1.checked_add(*v2)
+    }
+
+    pub fn sub(v1: &Integer, v2: &Integer) -> Option<Integer> {
+        v1.checked_sub(*v2)
+    }
+
+    pub fn mul(v1: &Integer, v2: &Integer) -> Option<Integer> {
+        v1.checked_mul(*v2)
+    }
+
+    pub fn div(v1: &Integer, v2: &Integer) -> Option<Integer> {
+        v1.checked_div(*v2)
+    }
+
+    pub fn rem(v1: &Integer, v2: &Integer) -> Option<Integer> {
+        v1.checked_rem(*v2)
+    }
+}
+
+#[cfg(feature = "bignum")]
+mod ops {
+    use super::{is_zero, Integer};
+
+    pub fn add(v1: &Integer, v2: &Integer) -> Option<Integer> {
+        Some(v1 + v2)
+    }
+
+    pub fn sub(v1: &Integer, v2: &Integer) -> Option<Integer> {
+        Some(v1 - v2)
+    }
+
+    pub fn mul(v1: &Integer, v2: &Integer) -> Option<Integer> {
+        Some(v1 * v2)
+    }
+
+    // Division truncates toward zero, like `i128`'s.
+    pub fn div(v1: &Integer, v2: &Integer) -> Option<Integer> {
+        if is_zero(v2) {
+            None
+        } else {
+            Some(v1 / v2)
+        }
+    }
+
+    pub fn rem(v1: &Integer, v2: &Integer) -> Option<Integer> {
+        if is_zero(v2) {
+            None
+        } else {
+            Some(v1 % v2)
+        }
+    }
+}
+
+pub use self::ops::{add, div, mul, rem, sub};
+
+/// An `Integer` for tests to write literals with.
+#[cfg(test)]
+#[allow(clippy::useless_conversion)]
+pub fn int(value: i128) -> Integer {
+    value.into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_truncating_division() {
+        let cases: [(i128, i128, i128, i128); 4] = [
+            (7, 2, 3, 1),
+            (-7, 2, -3, -1),
+            (7, -2, -3, 1),
+            (-7, -2, 3, -1),
+        ];
+        for (v1, v2, quotient, remainder) in cases.iter().cloned() {
+            let (v1, v2) = (Integer::from(v1), Integer::from(v2));
+            assert_eq!(Some(Integer::from(quotient)), div(&v1, &v2));
+            assert_eq!(Some(Integer::from(remainder)), rem(&v1, &v2));
+        }
+    }
+
+    #[test]
+    fn test_sexp_round_trip() {
+        let value = Integer::from(-42);
+        assert_eq!(Ok(value.clone()), from_sexp(to_sexp(value)));
+        assert_eq!(Err(SExp::symbol("a")), from_sexp(SExp::symbol("a")));
+    }
+
+    #[cfg(feature = "bignum")]
+    #[test]
+    fn test_beyond_i128() {
+        let max = Integer::from(i128::MAX);
+        let big = add(&max, &Integer::from(1)).unwrap();
+        assert_eq!(SExp::BigInteger(big.clone()), to_sexp(big.clone()));
+        assert_eq!(Some(max), sub(&big, &Integer::from(1)));
+        assert_eq!(None, to_index(&big));
+    }
+}
@@ -0,0 +1,48 @@
+// `Integer` is only `Copy` without `bignum`, so values are cloned.
+#![cfg_attr(not(feature = "bignum"), allow(clippy::clone_on_copy))]
+
+use dcpl::registry::{AnyLanguage, Registration};
+use dcpl::MetaCommand;
+
+mod check;
+mod debugger;
+mod denotation;
+#[cfg(test)]
+mod generate;
+mod integer;
+mod machine;
+mod program;
+mod read;
+mod sos;
+mod termination;
+mod top_level;
+mod trace;
+
+pub use crate::check::{check, Analysis, Point, Shape, Slot};
+pub use crate::debugger::{Breakpoint, Debugger, Pause, Step};
+pub use crate::denotation::Answer;
+pub use crate::machine::Machine;
+pub use crate::program::{Error as ProgramError, Limit, Limits, Program, StackValue};
+pub use crate::read::{BuiltIn, Command};
+pub use crate::sos::{Configuration, Derivation, Format, Rule, Steps};
+pub use crate::termination::{
+    analyze, command_energy, energy, sequence_energy, value_energy, Termination,
+};
+pub use crate::top_level::{Error, TopLevel, Value};
+pub use crate::trace::{table, TraceEvent};
+
+/// The `:programs` meta-command.
+pub fn programs_command() -> MetaCommand<TopLevel> {
+    MetaCommand::new(
+        "programs",
+        "list the defined programs",
+        |top_level: &mut TopLevel, _| Some(top_level.describe_programs()),
+    )
+}
+
+/// Postfix, for the `dcpl` launcher.
+pub fn registration() -> Registration {
+    Registration::new("postfix", &["pf", "pfx", "postfix"], || {
+        AnyLanguage::with_commands(TopLevel::new(), vec![programs_command()])
+    })
+}
@@ -0,0 +1,336 @@
+//! The machine postfix programs run on. Its state is the stack and
+//! the sequences of commands being executed, one for the program and
+//! one for each `exec` in progress, so a program can be run a command
+//! at a time, stopped, inspected and resumed.
+
+use dcpl::Interrupt;
+
+use crate::integer::Integer;
+use crate::program::{Error, Limit, Limits, Program, Stack, StackValue};
+use crate::read::{BuiltIn, Command};
+use crate::top_level::Error as TopLevelError;
+use crate::trace::TraceEvent;
+
+pub struct Machine {
+    stack: Stack,
+    frames: Vec<Frame>,
+    interrupt: Interrupt,
+    limits: Limits,
+    steps: u64,
+    trace: Option<Vec<TraceEvent>>,
+}
+
+/// A sequence of commands being executed.
+struct Frame {
+    commands: Vec<Command>,
+    next: usize,
+    /// The trace event of the `exec` that started the sequence, which
+    /// is finished along with it.
+    event: Option<usize>,
+}
+
+impl Machine {
+    /// A machine about to run `program` on `args`.
+    pub fn new(
+        program: &Program,
+        args: Vec<Integer>,
+        interrupt: Interrupt,
+        limits: Limits,
+    ) -> Result<Machine, TopLevelError> {
+        let num_args = args.len();
+        if program.num_args() != num_args {
+            return Err(TopLevelError::WrongNumberOfArgs {
+                expected: program.num_args(),
+                actual: num_args,
+            });
+        }
+        let stack = args.into_iter().rev().map(StackValue::from).collect();
+        Ok(Machine::start(
+            stack,
+            program.commands().to_vec(),
+            interrupt,
+            limits,
+        ))
+    }
+
+    /// A machine about to run `commands` on `stack`.
+    pub(crate) fn start(
+        stack: Stack,
+        commands: Vec<Command>,
+        interrupt: Interrupt,
+        limits: Limits,
+    ) -> Machine {
+        let mut machine = Machine {
+            stack,
+            frames: vec![Frame {
+                commands,
+                next: 0,
+                event: None,
+            }],
+            interrupt,
+            limits,
+            steps: 0,
+            trace: None,
+        };
+        machine.finish_frames();
+        machine
+    }
+
+    /// Record every command the machine runs from now on.
+    pub fn traced(self) -> Machine {
+        Machine {
+            trace: Some(vec![]),
+            ..self
+        }
+    }
+
+    /// The commands run so far, if the machine is traced.
+    pub fn take_trace(&mut self) -> Vec<TraceEvent> {
+        self.trace.take().unwrap_or_default()
+    }
+
+    /// The stack, bottom first.
+    pub fn stack(&self) -> &[StackValue] {
+        &self.stack.0
+    }
+
+    pub fn stack_mut(&mut self) -> &mut Vec<StackValue> {
+        &mut self.stack.0
+    }
+
+    /// The command that will run next, or `None` once the program has
+    /// finished or failed.
+    pub fn next_command(&self) -> Option<&Command> {
+        self.frames.last().map(|frame| &frame.commands[frame.next])
+    }
+
+    /// The commands left in the sequence being executed, starting with
+    /// the next one.
+    pub fn remaining(&self) -> &[Command] {
+        self.frames
+            .last()
+            .map_or(&[], |frame| &frame.commands[frame.next..])
+    }
+
+    /// Every command left to run, in the order they will run unless
+    /// an `exec` runs others in between.
+    pub fn pending(&self) -> impl Iterator<Item = &Command> {
+        self.frames
+            .iter()
+            .rev()
+            .flat_map(|frame| &frame.commands[frame.next..])
+    }
+
+    /// How many `exec`s the next command is nested in.
+    pub fn depth(&self) -> usize {
+        self.frames.len().saturating_sub(1)
+    }
+
+    /// Where the next command is in the sequence being executed.
+    pub fn index(&self) -> usize {
+        self.frames.last().map_or(0, |frame| frame.next)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Run the next command. `exec` only starts running its sequence,
+    /// so the command after it is the first in the sequence. After an
+    /// error the machine is finished.
+    pub fn step(&mut self) -> Result<(), Error> {
+        let result = self.try_step();
+        if let Err(error) = &result {
+            let frames = std::mem::take(&mut self.frames);
+            for event in frames.into_iter().filter_map(|frame| frame.event) {
+                self.finish_event(event, Err(error.clone()));
+            }
+        }
+        result
+    }
+
+    /// Run commands until the program finishes.
+    pub fn run(&mut self) -> Result<(), Error> {
+        while !self.is_finished() {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// The integer the finished program left on top of the stack.
+    pub fn result(mut self) -> Result<Integer, TopLevelError> {
+        match self.stack.pop()? {
+            StackValue::Integer(value) => Ok(value),
+            StackValue::ExecutableSequence(_) => Err(TopLevelError::FinalValueNotAnInteger),
+        }
+    }
+
+    fn try_step(&mut self) -> Result<(), Error> {
+        if self.is_finished() {
+            return Ok(());
+        }
+        if self.interrupt.is_triggered() {
+            return Err(Error::Interrupted);
+        }
+        if let Some(steps) = self.limits.steps {
+            if self.steps >= steps {
+                return Err(Error::ResourceExhausted(Limit::Steps(steps)));
+            }
+        }
+        self.steps += 1;
+
+        let frame = self.frames.last_mut().unwrap();
+        let command = frame.commands[frame.next].clone();
+        frame.next += 1;
+        let event = self.start_event(&command);
+        if let Err(error) = self.execute(command, event) {
+            if let Some(event) = event {
+                self.finish_event(event, Err(error.clone()));
+            }
+            return Err(error);
+        }
+        self.finish_frames();
+        Ok(())
+    }
+
+    fn execute(&mut self, command: Command, event: Option<usize>) -> Result<(), Error> {
+        match command {
+            Command::BuiltIn(BuiltIn::Exec) => {
+                let commands = self.stack.pop()?.into_ex_seq()?;
+                if let Some(depth) = self.limits.depth {
+                    if self.depth() >= depth {
+                        return Err(Error::ResourceExhausted(Limit::Depth(depth)));
+                    }
+                }
+                self.frames.push(Frame {
+                    commands,
+                    next: 0,
+                    event,
+                });
+            }
+            command => {
+                let stack = std::mem::replace(&mut self.stack, Stack(vec![]));
+                self.stack = Program::apply_command(stack, &command)?;
+                if let Some(event) = event {
+                    self.finish_event(event, Ok(self.stack.0.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Pop the sequences that have no commands left.
+    fn finish_frames(&mut self) {
+        while let Some(frame) = self.frames.last() {
+            if frame.next < frame.commands.len() {
+                break;
+            }
+            if let Some(event) = self.frames.pop().unwrap().event {
+                self.finish_event(event, Ok(self.stack.0.clone()));
+            }
+        }
+    }
+
+    fn start_event(&mut self, command: &Command) -> Option<usize> {
+        let depth = self.depth();
+        let before = &self.stack.0;
+        self.trace.as_mut().map(|trace| {
+            trace.push(TraceEvent {
+                command: command.clone(),
+                before: before.clone(),
+                after: Ok(vec![]),
+                depth,
+            });
+            trace.len() - 1
+        })
+    }
+
+    fn finish_event(&mut self, event: usize, after: Result<Vec<StackValue>, Error>) {
+        if let Some(trace) = self.trace.as_mut() {
+            trace[event].after = after;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::integer::int;
+
+    fn commands(text: &str) -> Vec<Command> {
+        let sexp = dcpl::SExpParser::parse_line(text).unwrap();
+        match Command::read(sexp).unwrap() {
+            Command::ExecutableSequence(commands) => commands,
+            _ => panic!("expected a sequence"),
+        }
+    }
+
+    fn machine(text: &str, args: Vec<i128>) -> Machine {
+        let program = Program::new(args.len(), commands(text));
+        let args = args.into_iter().map(int).collect();
+        Machine::new(&program, args, Interrupt::new(), Limits::default()).unwrap()
+    }
+
+    #[test]
+    fn test_step_into_exec() {
+        let mut machine = machine("((2 mul) exec 1 add)", vec![5]);
+        assert_eq!(commands("((2 mul) exec 1 add)"), machine.remaining());
+        machine.step().unwrap();
+        machine.step().unwrap();
+        assert_eq!(1, machine.depth());
+        assert_eq!(0, machine.index());
+        assert_eq!(commands("(2 mul)"), machine.remaining());
+        machine.step().unwrap();
+        machine.step().unwrap();
+        // The sequence is done, so the machine is back in the program.
+        assert_eq!(0, machine.depth());
+        assert_eq!(2, machine.index());
+        assert_eq!(&[StackValue::from(10)], machine.stack());
+        machine.run().unwrap();
+        assert!(machine.is_finished());
+        assert_eq!(Ok(int(11)), machine.result());
+    }
+
+    #[test]
+    fn test_modify_stack() {
+        let mut machine = machine("(mul)", vec![5, 6]);
+        machine.stack_mut()[0] = StackValue::from(7);
+        machine.run().unwrap();
+        assert_eq!(Ok(int(35)), machine.result());
+    }
+
+    #[test]
+    fn test_error_finishes() {
+        let mut machine = machine("(1 0 div 2)", vec![]);
+        assert_eq!(Ok(()), machine.step());
+        assert_eq!(Ok(()), machine.step());
+        assert_eq!(Err(Error::DivideByZero), machine.step());
+        assert!(machine.is_finished());
+        assert_eq!(None, machine.next_command());
+    }
+
+    #[test]
+    fn test_empty_program() {
+        let machine = machine("()", vec![3]);
+        assert!(machine.is_finished());
+        assert_eq!(Ok(int(3)), machine.result());
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        // Deeper than the default limit allows; the machine doesn't recurse.
+        let mut commands = vec![Command::from(1)];
+        for _ in 0..2000 {
+            commands = vec![
+                Command::ExecutableSequence(commands),
+                Command::BuiltIn(BuiltIn::Exec),
+            ];
+        }
+        let program = Program::new(0, commands);
+        let mut machine =
+            Machine::new(&program, vec![], Interrupt::new(), Limits::unlimited()).unwrap();
+        machine.run().unwrap();
+        assert_eq!(Ok(int(1)), machine.result());
+    }
+}
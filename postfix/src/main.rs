@@ -1,13 +1,14 @@
-use dcpl::Interpreter;
+use std::env;
+use std::process;
 
-mod program;
-mod read;
-mod top_level;
+use dcpl::cli;
 
-use crate::top_level::TopLevel;
+use postfix::{programs_command, TopLevel};
 
 fn main() {
-    let mut top_level = TopLevel::new();
-    let mut interpreter = Interpreter::new("Postfix", move |expr| top_level.interpret(expr));
-    interpreter.run();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let code = cli::run(&args, TopLevel::new, |interpreter| {
+        interpreter.add_command(programs_command())
+    });
+    process::exit(code);
 }
@@ -1,19 +1,91 @@
+use std::fmt;
 use std::iter::FromIterator;
 
+use dcpl::Interrupt;
+
+use crate::denotation::{self, Answer};
+use crate::integer::{self, Integer};
+use crate::machine::Machine;
 use crate::read::{BuiltIn, Command};
 use crate::top_level::Error as TopLevelError;
+use crate::trace::TraceEvent;
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Error {
     NotEnoughValues,
     NotANumber,
     NotAnExecutableSequence,
+    Interrupted,
+    DivideByZero,
+    Overflow,
+    /// `nget` was asked for a value that isn't on the stack.
+    IndexOutOfRange(Integer),
+    ResourceExhausted(Limit),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::NotEnoughValues => write!(f, "not enough values on the stack"),
+            Error::NotANumber => write!(f, "a value that should be an integer isn't"),
+            Error::NotAnExecutableSequence => write!(f, "exec of a value that isn't a sequence"),
+            Error::Interrupted => write!(f, "interrupted"),
+            Error::DivideByZero => write!(f, "division by zero"),
+            Error::Overflow => write!(f, "integer overflow"),
+            Error::IndexOutOfRange(index) => write!(f, "nget index {} is out of range", index),
+            Error::ResourceExhausted(Limit::Steps(steps)) => {
+                write!(f, "ran more than {} commands", steps)
+            }
+            Error::ResourceExhausted(Limit::Depth(depth)) => {
+                write!(f, "execs nested more than {} deep", depth)
+            }
+        }
+    }
 }
 
+/// A limit a program ran into.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Limit {
+    Steps(u64),
+    Depth(usize),
+}
+
+/// How much a program may do before it is stopped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    /// The most commands the program may execute, counting those run
+    /// by `exec`.
+    pub steps: Option<u64>,
+    /// How deeply `exec`s may nest. This is limited by default, so a
+    /// program that recurses without end is stopped.
+    pub depth: Option<usize>,
+}
+
+impl Limits {
+    pub const DEFAULT_DEPTH: usize = 1000;
+
+    pub fn unlimited() -> Limits {
+        Limits {
+            steps: None,
+            depth: None,
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            steps: None,
+            depth: Some(Limits::DEFAULT_DEPTH),
+        }
+    }
+}
+
+/// A value on the stack of a running program.
 #[derive(Clone, Debug, PartialEq)]
-enum StackValue {
+pub enum StackValue {
     ExecutableSequence(Vec<Command>),
-    Integer(i128),
+    Integer(Integer),
 }
 
 impl StackValue {
@@ -24,7 +96,7 @@ impl StackValue {
         }
     }
 
-    pub fn into_integer(self) -> Result<i128, Error> {
+    pub fn into_integer(self) -> Result<Integer, Error> {
         match self {
             StackValue::Integer(value) => Ok(value),
             _ => Err(Error::NotANumber),
@@ -39,6 +111,17 @@ impl StackValue {
     }
 }
 
+impl fmt::Display for StackValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            StackValue::ExecutableSequence(commands) => {
+                write!(f, "{}", Command::ExecutableSequence(commands.clone()))
+            }
+            StackValue::Integer(value) => write!(f, "{}", value),
+        }
+    }
+}
+
 impl FromIterator<Command> for StackValue {
     fn from_iter<T>(iter: T) -> Self
     where
@@ -54,14 +137,21 @@ impl From<Vec<Command>> for StackValue {
     }
 }
 
+impl From<Integer> for StackValue {
+    fn from(number: Integer) -> StackValue {
+        StackValue::Integer(number)
+    }
+}
+
+#[cfg(feature = "bignum")]
 impl From<i128> for StackValue {
     fn from(number: i128) -> StackValue {
-        StackValue::Integer(number)
+        StackValue::Integer(number.into())
     }
 }
 
 #[derive(Debug, PartialEq)]
-struct Stack(Vec<StackValue>);
+pub(crate) struct Stack(pub(crate) Vec<StackValue>);
 
 impl Stack {
     pub fn pop(&mut self) -> Result<StackValue, Error> {
@@ -80,22 +170,19 @@ impl Stack {
         Ok(self)
     }
 
+    /// Copy the integer `vindex` values down from the top of the stack,
+    /// counting from 1.
     pub fn nget(mut self) -> Result<Stack, Error> {
         let vindex = self.pop()?.into_integer()?;
         let len = self.0.len();
-        let vi = self
-            .0
-            .get(len - vindex as usize)
-            .ok_or(Error::NotEnoughValues)?;
+        let vi = integer::to_index(&vindex)
+            .filter(|&index| index >= 1 && index <= len)
+            .map(|index| &self.0[len - index])
+            .ok_or(Error::IndexOutOfRange(vindex))?;
         vi.assert_integer()?;
         self.push(vi.clone());
         Ok(self)
     }
-
-    pub fn exec(self, commands: Vec<Command>) -> Result<Stack, Error> {
-        let result = commands.iter().try_fold(self, Program::apply_command)?;
-        Ok(result)
-    }
 }
 
 impl FromIterator<StackValue> for Stack {
@@ -114,7 +201,7 @@ pub struct Program {
 }
 
 macro_rules! arith_op {
-    { $stack:ident, $op:tt } => {{
+    { $stack:ident, $op:ident } => {{
         let v1 = $stack
             .pop()?
             .into_integer()?;
@@ -122,7 +209,23 @@ macro_rules! arith_op {
             .pop()?
             .into_integer()?;
 
-        $stack.push(StackValue::Integer(v2 $op v1));
+        let result = integer::$op(&v2, &v1).ok_or(Error::Overflow)?;
+        $stack.push(StackValue::Integer(result));
+        Ok($stack)
+    }};
+    { $stack:ident, $op:ident, divisor } => {{
+        let v1 = $stack
+            .pop()?
+            .into_integer()?;
+        let v2 = $stack
+            .pop()?
+            .into_integer()?;
+
+        if integer::is_zero(&v1) {
+            return Err(Error::DivideByZero);
+        }
+        let result = integer::$op(&v2, &v1).ok_or(Error::Overflow)?;
+        $stack.push(StackValue::Integer(result));
         Ok($stack)
     }};
 }
@@ -136,7 +239,7 @@ macro_rules! bool_op {
             .pop()?
             .into_integer()?;
 
-        $stack.push(StackValue::Integer(if v2 $op v1 {1} else {0}));
+        $stack.push(StackValue::Integer(Integer::from(if v2 $op v1 {1} else {0})));
         Ok($stack)
     }};
 }
@@ -146,30 +249,93 @@ impl Program {
         Program { num_args, commands }
     }
 
-    pub fn apply(&self, args: Vec<i128>) -> Result<i128, TopLevelError> {
-        let num_args = args.len();
-        if self.num_args != num_args {
-            return Err(TopLevelError::WrongNumberOfArgs {
-                expected: self.num_args,
-                actual: num_args,
-            });
-        }
-        let stack: Stack = args.into_iter().rev().map(StackValue::from).collect();
-        let mut final_stack = self
-            .commands
-            .iter()
-            .try_fold(stack, Program::apply_command)?;
-        match final_stack.pop()? {
-            StackValue::Integer(value) => Ok(value),
-            StackValue::ExecutableSequence(_) => Err(TopLevelError::FinalValueNotAnInteger),
-        }
+    pub fn num_args(&self) -> usize {
+        self.num_args
+    }
+
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    /// Run the program on `args` with the default limits, giving up
+    /// with `Error::Interrupted` as soon as `interrupt` is triggered.
+    pub fn apply(
+        &self,
+        args: Vec<Integer>,
+        interrupt: &Interrupt,
+    ) -> Result<Integer, TopLevelError> {
+        self.apply_with_limits(args, interrupt, Limits::default())
+    }
+
+    /// The answer the program's meaning in DCPL's denotational
+    /// semantics gives for `args`. It agrees with `apply`, except that
+    /// every error is the same `Answer::Error`.
+    pub fn denote(&self, args: Vec<Integer>) -> Answer {
+        denotation::program(self, args)
+    }
+
+    /// Run the program on `args` like `apply`, but stop it with
+    /// `Error::ResourceExhausted` if it goes past `limits`.
+    ///
+    /// ```
+    /// use dcpl::Interrupt;
+    /// use postfix::{BuiltIn, Command, Limit, Limits, Program, ProgramError};
+    ///
+    /// // (1 nget mul), which squares its argument in three commands.
+    /// let commands = vec![Command::from(1), BuiltIn::Nget.into(), BuiltIn::Mul.into()];
+    /// let square = Program::new(1, commands);
+    /// let interrupt = Interrupt::new();
+    /// let answer = square.apply_with_limits(vec![5.into()], &interrupt, Limits::default());
+    /// assert_eq!(Ok(25.into()), answer);
+    ///
+    /// let limits = Limits {
+    ///     steps: Some(2),
+    ///     depth: None,
+    /// };
+    /// let error = ProgramError::ResourceExhausted(Limit::Steps(2));
+    /// let answer = square.apply_with_limits(vec![5.into()], &interrupt, limits);
+    /// assert_eq!(Err(error.into()), answer);
+    /// ```
+    pub fn apply_with_limits(
+        &self,
+        args: Vec<Integer>,
+        interrupt: &Interrupt,
+        limits: Limits,
+    ) -> Result<Integer, TopLevelError> {
+        let mut machine = Machine::new(self, args, interrupt.clone(), limits)?;
+        machine.run()?;
+        machine.result()
+    }
+
+    /// Run the program on `args` like `apply_with_limits`, recording
+    /// every command it runs. The trace is returned whether or not the
+    /// program succeeds, and ends at the command that failed.
+    pub fn trace(
+        &self,
+        args: Vec<Integer>,
+        interrupt: &Interrupt,
+        limits: Limits,
+    ) -> (Vec<TraceEvent>, Result<Integer, TopLevelError>) {
+        let mut machine = match Machine::new(self, args, interrupt.clone(), limits) {
+            Ok(machine) => machine.traced(),
+            Err(error) => return (vec![], Err(error)),
+        };
+        let result = machine.run();
+        let trace = machine.take_trace();
+        (
+            trace,
+            result
+                .map_err(TopLevelError::from)
+                .and_then(|()| machine.result()),
+        )
     }
 
-    fn apply_command(mut stack: Stack, command: &Command) -> Result<Stack, Error> {
+    /// Apply any command but `exec`, which the machine runs itself.
+    pub(crate) fn apply_command(mut stack: Stack, command: &Command) -> Result<Stack, Error> {
         use crate::read::Command::*;
         match command {
             Integer(inner) => {
-                stack.push(StackValue::from(*inner));
+                stack.push(StackValue::from(inner.clone()));
                 Ok(stack)
             }
             ExecutableSequence(inner) => {
@@ -183,11 +349,11 @@ impl Program {
     fn apply_builtin(mut stack: Stack, builtin: &BuiltIn) -> Result<Stack, Error> {
         use crate::read::BuiltIn::*;
         match builtin {
-            Add => arith_op!(stack, +),
-            Sub => arith_op!(stack, -),
-            Mul => arith_op!(stack, *),
-            Div => arith_op!(stack, /),
-            Rem => arith_op!(stack, %),
+            Add => arith_op!(stack, add),
+            Sub => arith_op!(stack, sub),
+            Mul => arith_op!(stack, mul),
+            Div => arith_op!(stack, div, divisor),
+            Rem => arith_op!(stack, rem, divisor),
             Eq => bool_op!(stack, ==),
             Gt => bool_op!(stack, >),
             Lt => bool_op!(stack, <),
@@ -200,7 +366,7 @@ impl Program {
                 let v1 = stack.pop()?;
                 let v2 = stack.pop()?;
                 let v3 = stack.pop()?;
-                if v3.into_integer()? == 0 {
+                if integer::is_zero(&v3.into_integer()?) {
                     stack.push(v1);
                 } else {
                     stack.push(v2);
@@ -208,10 +374,7 @@ impl Program {
                 Ok(stack)
             }
             Nget => stack.nget(),
-            Exec => {
-                let top = stack.pop()?;
-                stack.exec(top.into_ex_seq()?)
-            }
+            Exec => unreachable!("`exec` is run by the machine"),
         }
     }
 }
@@ -220,6 +383,15 @@ impl Program {
 mod test {
     use super::*;
 
+    use crate::integer::int;
+
+    fn apply_builtin(stack: Stack, builtin: &BuiltIn) -> Result<Stack, Error> {
+        let command = Command::BuiltIn(builtin.clone());
+        let mut machine = Machine::start(stack, vec![command], Interrupt::new(), Limits::default());
+        machine.run()?;
+        Ok(Stack(machine.stack().to_vec()))
+    }
+
     macro_rules! stack {
         { $($val:expr),* }=> {{
             let v: Vec<i128> = vec![ $($val),* ];
@@ -231,7 +403,7 @@ mod test {
         { $name:ident : $operator:expr => [ $($stack_val:expr),* ] == $expected:expr } => {
             #[test]
             fn $name() {
-                assert_eq!(Ok(stack![$expected]), Program::apply_builtin(stack![ $($stack_val),* ], &$operator));
+                assert_eq!(Ok(stack![$expected]), apply_builtin(stack![ $($stack_val),* ], &$operator));
             }
         }
     }
@@ -249,7 +421,7 @@ mod test {
         { $name:ident : $operator:expr => [ $($stack_val:expr),* ] -> $expected:tt } => {
             #[test]
             fn $name() {
-                assert_eq!(Ok(boolean!($expected)), Program::apply_builtin(stack![ $($stack_val),* ], &$operator));
+                assert_eq!(Ok(boolean!($expected)), apply_builtin(stack![ $($stack_val),* ], &$operator));
             }
         };
     }
@@ -272,23 +444,20 @@ mod test {
     fn test_pop_empty() {
         assert_eq!(
             Err(Error::NotEnoughValues),
-            Program::apply_builtin(stack![], &BuiltIn::Pop)
+            apply_builtin(stack![], &BuiltIn::Pop)
         );
     }
 
     #[test]
     fn test_pop() {
-        assert_eq!(
-            Ok(stack![1]),
-            Program::apply_builtin(stack![1, 2], &BuiltIn::Pop)
-        );
+        assert_eq!(Ok(stack![1]), apply_builtin(stack![1, 2], &BuiltIn::Pop));
     }
 
     #[test]
     fn test_swap_empty() {
         assert_eq!(
             Err(Error::NotEnoughValues),
-            Program::apply_builtin(stack![], &BuiltIn::Swap)
+            apply_builtin(stack![], &BuiltIn::Swap)
         );
     }
 
@@ -296,7 +465,7 @@ mod test {
     fn test_swap_one() {
         assert_eq!(
             Err(Error::NotEnoughValues),
-            Program::apply_builtin(stack![1], &BuiltIn::Swap)
+            apply_builtin(stack![1], &BuiltIn::Swap)
         );
     }
 
@@ -304,58 +473,103 @@ mod test {
     fn test_swap() {
         assert_eq!(
             Ok(stack![1, 9]),
-            Program::apply_builtin(stack![9, 1], &BuiltIn::Swap)
+            apply_builtin(stack![9, 1], &BuiltIn::Swap)
         )
     }
 
     #[test]
     fn test_sel_then() {
-        assert_eq!(
-            Ok(stack![3]),
-            Program::apply_builtin(stack![0, 2, 3], &BuiltIn::Sel)
-        )
+        assert_eq!(Ok(stack![3]), apply_builtin(stack![0, 2, 3], &BuiltIn::Sel))
     }
 
     #[test]
     fn test_sel_else() {
-        assert_eq!(
-            Ok(stack![2]),
-            Program::apply_builtin(stack![1, 2, 3], &BuiltIn::Sel)
-        )
+        assert_eq!(Ok(stack![2]), apply_builtin(stack![1, 2, 3], &BuiltIn::Sel))
     }
 
     #[test]
     fn test_nget_exec_seq() {
-        let ex_seq: StackValue = StackValue::from(vec![Command::Integer(1)]);
-        let stack = Stack(vec![ex_seq, StackValue::Integer(1)]);
-        assert_eq!(
-            Err(Error::NotANumber),
-            Program::apply_builtin(stack, &BuiltIn::Nget)
-        )
+        let ex_seq: StackValue = StackValue::from(vec![Command::from(1)]);
+        let stack = Stack(vec![ex_seq, StackValue::from(1)]);
+        assert_eq!(Err(Error::NotANumber), apply_builtin(stack, &BuiltIn::Nget))
     }
 
     #[test]
     fn test_nget() {
         assert_eq!(
             Ok(stack![4, 4]),
-            Program::apply_builtin(stack![4, 1], &BuiltIn::Nget)
+            apply_builtin(stack![4, 1], &BuiltIn::Nget)
         )
     }
 
+    #[test]
+    fn test_nget_out_of_range() {
+        for index in &[0, -1, 3, i128::MAX, i128::MIN] {
+            assert_eq!(
+                Err(Error::IndexOutOfRange(int(*index))),
+                apply_builtin(stack![4, 5, *index], &BuiltIn::Nget)
+            );
+        }
+    }
+
+    #[test]
+    fn test_divide_by_zero() {
+        for builtin in &[BuiltIn::Div, BuiltIn::Rem] {
+            assert_eq!(
+                Err(Error::DivideByZero),
+                apply_builtin(stack![1, 0], builtin)
+            );
+        }
+    }
+
+    #[cfg(not(feature = "bignum"))]
+    #[test]
+    fn test_overflow() {
+        let cases = vec![
+            (BuiltIn::Add, stack![i128::MAX, 1]),
+            (BuiltIn::Sub, stack![i128::MIN, 1]),
+            (BuiltIn::Mul, stack![i128::MAX, 2]),
+            (BuiltIn::Div, stack![i128::MIN, -1]),
+            (BuiltIn::Rem, stack![i128::MIN, -1]),
+        ];
+        for (builtin, stack) in cases {
+            assert_eq!(Err(Error::Overflow), apply_builtin(stack, &builtin));
+        }
+    }
+
+    #[cfg(feature = "bignum")]
+    #[test]
+    fn test_no_overflow() {
+        let max = int(i128::MAX);
+        let min = int(i128::MIN);
+        assert_eq!(
+            Ok(Stack(vec![StackValue::Integer(&max + 1)])),
+            apply_builtin(stack![i128::MAX, 1], &BuiltIn::Add)
+        );
+        assert_eq!(
+            Ok(Stack(vec![StackValue::Integer(-&min)])),
+            apply_builtin(stack![i128::MIN, -1], &BuiltIn::Div)
+        );
+        assert_eq!(
+            Ok(Stack(vec![StackValue::Integer(&max * 2)])),
+            apply_builtin(stack![i128::MAX, 2], &BuiltIn::Mul)
+        );
+    }
+
     #[test]
     fn test_exec_number() {
         assert_eq!(
             Err(Error::NotAnExecutableSequence),
-            Program::apply_builtin(stack![1], &BuiltIn::Exec)
+            apply_builtin(stack![1], &BuiltIn::Exec)
         )
     }
 
     #[test]
     fn test_exec() {
         let ex_seq: StackValue =
-            StackValue::from(vec![Command::Integer(2), Command::BuiltIn(BuiltIn::Mul)]);
-        let stack = Stack(vec![StackValue::Integer(3), ex_seq]);
-        assert_eq!(Ok(stack![6]), Program::apply_builtin(stack, &BuiltIn::Exec))
+            StackValue::from(vec![Command::from(2), Command::BuiltIn(BuiltIn::Mul)]);
+        let stack = Stack(vec![StackValue::from(3), ex_seq]);
+        assert_eq!(Ok(stack![6]), apply_builtin(stack, &BuiltIn::Exec))
     }
 
     macro_rules! command {
@@ -371,8 +585,8 @@ mod test {
                 use crate::read::BuiltIn::*;
                 let commands = vec![ $( command!( $command ) ),* ];
                 let program = Program::new($num_args, commands);
-                let args: Vec<i128> = vec![ $($args),* ];
-                assert_eq!($expected, program.apply(args));
+                let args = vec![ $(int($args)),* ];
+                assert_eq!($expected.map(int), program.apply(args, &Interrupt::new()));
             }
         }
     }
@@ -388,4 +602,91 @@ mod test {
 
     postfix_test!(test_pf_3_minus_4: (postfix 1 4 Sub) [3] -> Ok(-1));
 
+    #[test]
+    fn test_interrupted() {
+        let program = Program::new(0, vec![Command::from(1)]);
+        let interrupt = Interrupt::new();
+        interrupt.trigger();
+        assert_eq!(
+            Err(Error::Interrupted.into()),
+            program.apply(vec![], &interrupt)
+        );
+    }
+
+    /// `((((1) exec) exec) exec) exec`, nested `depth` deep.
+    fn nested(depth: usize) -> Program {
+        let mut commands = vec![Command::from(1)];
+        for _ in 0..depth {
+            commands = vec![
+                Command::ExecutableSequence(commands),
+                Command::BuiltIn(BuiltIn::Exec),
+            ];
+        }
+        Program::new(0, commands)
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let limits = Limits {
+            steps: Some(4),
+            depth: None,
+        };
+        // Two commands at the top and three in the sequence.
+        let sequence = vec![
+            Command::from(1),
+            Command::from(2),
+            Command::BuiltIn(BuiltIn::Add),
+        ];
+        let program = Program::new(
+            0,
+            vec![
+                Command::ExecutableSequence(sequence),
+                Command::BuiltIn(BuiltIn::Exec),
+            ],
+        );
+        assert_eq!(
+            Err(Error::ResourceExhausted(Limit::Steps(4)).into()),
+            program.apply_with_limits(vec![], &Interrupt::new(), limits)
+        );
+        let limits = Limits {
+            steps: Some(5),
+            ..limits
+        };
+        assert_eq!(
+            Ok(int(3)),
+            program.apply_with_limits(vec![], &Interrupt::new(), limits)
+        );
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        let limits = Limits {
+            steps: None,
+            depth: Some(3),
+        };
+        assert_eq!(
+            Ok(int(1)),
+            nested(3).apply_with_limits(vec![], &Interrupt::new(), limits)
+        );
+        assert_eq!(
+            Err(Error::ResourceExhausted(Limit::Depth(3)).into()),
+            nested(4).apply_with_limits(vec![], &Interrupt::new(), limits)
+        );
+    }
+
+    #[test]
+    fn test_default_depth_limit() {
+        assert_eq!(
+            Err(Error::ResourceExhausted(Limit::Depth(Limits::DEFAULT_DEPTH)).into()),
+            nested(Limits::DEFAULT_DEPTH + 1).apply(vec![], &Interrupt::new())
+        );
+        assert_eq!(
+            Ok(int(1)),
+            nested(Limits::DEFAULT_DEPTH + 1).apply_with_limits(
+                vec![],
+                &Interrupt::new(),
+                Limits::unlimited()
+            )
+        );
+    }
 }
@@ -1,7 +1,10 @@
+use std::fmt;
 use std::iter::FromIterator;
 
 use dcpl::SExp;
 
+use crate::integer::{self, Integer};
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum BuiltIn {
     Add,
@@ -20,6 +23,22 @@ pub enum BuiltIn {
 }
 
 impl BuiltIn {
+    pub const ALL: &'static [BuiltIn] = &[
+        BuiltIn::Add,
+        BuiltIn::Div,
+        BuiltIn::Eq,
+        BuiltIn::Exec,
+        BuiltIn::Gt,
+        BuiltIn::Lt,
+        BuiltIn::Mul,
+        BuiltIn::Nget,
+        BuiltIn::Pop,
+        BuiltIn::Rem,
+        BuiltIn::Sel,
+        BuiltIn::Sub,
+        BuiltIn::Swap,
+    ];
+
     pub fn read(name: String) -> Result<BuiltIn, Error> {
         use self::BuiltIn::*;
         match name.as_ref() {
@@ -40,12 +59,58 @@ impl BuiltIn {
             _ => Err(Error::UnknownBuiltin(name)),
         }
     }
+
+    pub fn name(&self) -> &'static str {
+        use self::BuiltIn::*;
+        match self {
+            Add => "add",
+            Div => "div",
+            Eq => "eq",
+            Exec => "exec",
+            Gt => "gt",
+            Lt => "lt",
+            Mul => "mul",
+            Nget => "nget",
+            Pop => "pop",
+            Rem => "rem",
+            Sel => "sel",
+            Sub => "sub",
+            Swap => "swap",
+        }
+    }
+
+    /// What the command does to the stack, where `v1` is the top value
+    /// and `v2` the one below it.
+    pub fn doc(&self) -> &'static str {
+        use self::BuiltIn::*;
+        match self {
+            Add => "add: pop v1 and v2, push v2 + v1",
+            Div => "div: pop v1 and v2, push v2 / v1",
+            Eq => "eq: pop v1 and v2, push 1 if v2 = v1 and 0 otherwise",
+            Exec => "exec: pop an executable sequence and run its commands",
+            Gt => "gt: pop v1 and v2, push 1 if v2 > v1 and 0 otherwise",
+            Lt => "lt: pop v1 and v2, push 1 if v2 < v1 and 0 otherwise",
+            Mul => "mul: pop v1 and v2, push v2 * v1",
+            Nget => "nget: pop an index i, push a copy of the i-th value from the top",
+            Pop => "pop: discard the top value",
+            Rem => "rem: pop v1 and v2, push the remainder of v2 / v1",
+            Sel => "sel: pop v1, v2 and v3, push v1 if v3 is 0 and v2 otherwise",
+            Sub => "sub: pop v1 and v2, push v2 - v1",
+            Swap => "swap: exchange the top two values",
+        }
+    }
+}
+
+impl fmt::Display for BuiltIn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}", self.name())
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Command {
     ExecutableSequence(Vec<Command>),
-    Integer(i128),
+    Integer(Integer),
     BuiltIn(BuiltIn),
 }
 
@@ -58,9 +123,16 @@ impl FromIterator<Command> for Command {
     }
 }
 
+impl From<Integer> for Command {
+    fn from(number: Integer) -> Command {
+        Command::Integer(number)
+    }
+}
+
+#[cfg(feature = "bignum")]
 impl From<i128> for Command {
     fn from(number: i128) -> Command {
-        Command::Integer(number)
+        Command::Integer(number.into())
     }
 }
 
@@ -70,16 +142,49 @@ impl From<BuiltIn> for Command {
     }
 }
 
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Command::ExecutableSequence(commands) => {
+                write!(f, "(")?;
+                let mut sep = "";
+                for command in commands {
+                    write!(f, "{}{}", sep, command)?;
+                    sep = " ";
+                }
+                write!(f, ")")
+            }
+            Command::Integer(value) => write!(f, "{}", value),
+            Command::BuiltIn(builtin) => write!(f, "{}", builtin),
+        }
+    }
+}
+
 impl Command {
     pub fn read(sexp: SExp) -> Result<Command, Error> {
         use dcpl::SExp::*;
+        let sexp = match integer::from_sexp(sexp) {
+            Ok(val) => return Ok(Command::Integer(val)),
+            Err(sexp) => sexp,
+        };
         match sexp {
             List(exprs) => Ok(Command::ExecutableSequence(Command::read_ex_seq(exprs)?)),
-            Integer(val) => Ok(Command::Integer(val)),
             Symbol(name) => Ok(Command::BuiltIn(BuiltIn::read(name)?)),
 
             Float(_) => Err(Error::UsingFloat),
             String(_) => Err(Error::UsingString),
+            _ => unreachable!("integers are read above"),
+        }
+    }
+
+    /// The s-expression that `read` turns back into this command.
+    pub fn to_sexp(&self) -> SExp {
+        match self {
+            Command::ExecutableSequence(commands) => {
+                SExp::List(commands.iter().map(Command::to_sexp).collect())
+            }
+            Command::Integer(value) => integer::to_sexp(value.clone()),
+            Command::BuiltIn(builtin) => SExp::symbol(builtin.name()),
         }
     }
 
@@ -95,12 +200,24 @@ pub enum Error {
     UsingString,
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Error::UnknownBuiltin(name) => write!(f, "unknown command `{}`", name),
+            Error::UsingFloat => write!(f, "floats aren't postfix values"),
+            Error::UsingString => write!(f, "strings aren't postfix values"),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::BuiltIn::*;
     use super::Command::*;
     use super::*;
 
+    use crate::integer::int;
+
     use dcpl::SExpParser;
 
     fn read_str(sexp_str: impl AsRef<str>) -> Result<Command, Error> {
@@ -110,14 +227,18 @@ mod test {
     #[test]
     fn test_read_executable_sequence() {
         assert_eq!(
-            Ok(ExecutableSequence(vec![Integer(1), Integer(2), Integer(3)])),
+            Ok(ExecutableSequence(vec![
+                Integer(int(1)),
+                Integer(int(2)),
+                Integer(int(3))
+            ])),
             read_str("(1 2 3)")
         )
     }
 
     #[test]
     fn test_read_integer() {
-        assert_eq!(Ok(Integer(10)), read_str("10"));
+        assert_eq!(Ok(Integer(int(10))), read_str("10"));
     }
 
     #[test]
@@ -174,6 +295,28 @@ mod test {
         assert_eq!(Ok(BuiltIn(Swap)), read_str("swap"));
     }
 
+    #[test]
+    fn test_builtin_names_round_trip() {
+        for builtin in super::BuiltIn::ALL {
+            assert_eq!(
+                Ok(builtin.clone()),
+                super::BuiltIn::read(builtin.name().into())
+            );
+        }
+    }
+
+    #[test]
+    fn test_display_command() {
+        let command = read_str("(1 (2 swap) exec)").unwrap();
+        assert_eq!("(1 (2 swap) exec)", command.to_string());
+    }
+
+    #[test]
+    fn test_to_sexp_round_trip() {
+        let command = read_str("(1 (2 swap) exec)").unwrap();
+        assert_eq!(Ok(command.clone()), Command::read(command.to_sexp()));
+    }
+
     #[test]
     fn test_read_float() {
         assert_eq!(Err(Error::UsingFloat), read_str("10.0"));
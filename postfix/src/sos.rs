@@ -0,0 +1,549 @@
+//! Postfix's small-step operational semantics, from DCPL's rewrite
+//! rules. A configuration is the commands left to run and the stack,
+//! and each step rewrites one configuration to the next by the one
+//! rule that matches it. A program's answer is the integer on top of
+//! the stack once no commands are left; a configuration no rule
+//! matches is stuck, with the error `Program::apply` gives there.
+//!
+//! Each step runs one command, so a step limit stops a derivation
+//! after as many steps as it stops the machine after commands. The
+//! rules put an `exec`'d sequence in front of the commands left instead
+//! of nesting it, so there is no depth to limit.
+
+use std::fmt;
+
+use dcpl::Interrupt;
+
+use crate::integer::{self, Integer};
+use crate::program::{Error, Limit, Limits, Program, StackValue};
+use crate::read::{BuiltIn, Command};
+use crate::top_level::Error as TopLevelError;
+use crate::trace::stack;
+
+/// The commands left to run and the stack.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Configuration {
+    /// The commands left to run, next first.
+    pub commands: Vec<Command>,
+    /// The stack, bottom first.
+    pub stack: Vec<StackValue>,
+}
+
+/// DCPL's names for the rewrite rules.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rule {
+    Num,
+    Seq,
+    Pop,
+    Swap,
+    Arithop,
+    RelopTrue,
+    RelopFalse,
+    Nget,
+    SelTrue,
+    SelFalse,
+    Execute,
+}
+
+impl Rule {
+    pub fn name(&self) -> &'static str {
+        use self::Rule::*;
+        match self {
+            Num => "num",
+            Seq => "seq",
+            Pop => "pop",
+            Swap => "swap",
+            Arithop => "arithop",
+            RelopTrue => "relop-true",
+            RelopFalse => "relop-false",
+            Nget => "nget",
+            SelTrue => "sel-true",
+            SelFalse => "sel-false",
+            Execute => "execute",
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "[{}]", self.name())
+    }
+}
+
+impl Configuration {
+    /// The configuration `program` starts in on `args`.
+    pub fn initial(program: &Program, args: Vec<Integer>) -> Result<Configuration, TopLevelError> {
+        if program.num_args() != args.len() {
+            return Err(TopLevelError::wrong_number(program.num_args(), args.len()));
+        }
+        Ok(Configuration {
+            commands: program.commands().to_vec(),
+            stack: args.into_iter().rev().map(StackValue::from).collect(),
+        })
+    }
+
+    /// Whether no commands are left, so no rule applies.
+    pub fn is_final(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// The answer of a final configuration: the integer on top of its
+    /// stack.
+    pub fn answer(&self) -> Result<Integer, TopLevelError> {
+        match self.stack.last() {
+            Some(StackValue::Integer(value)) => Ok(value.clone()),
+            Some(StackValue::ExecutableSequence(_)) => Err(TopLevelError::FinalValueNotAnInteger),
+            None => Err(Error::NotEnoughValues.into()),
+        }
+    }
+
+    /// The rule that matches this configuration and the configuration
+    /// it rewrites to, `None` if it is final, or the error it is stuck
+    /// with.
+    pub fn step(&self) -> Option<Result<(Rule, Configuration), Error>> {
+        let (command, rest) = self.commands.split_first()?;
+        let mut stack = self.stack.clone();
+        let mut commands = rest.to_vec();
+        let rule = match command {
+            Command::Integer(value) => {
+                stack.push(StackValue::Integer(value.clone()));
+                Rule::Num
+            }
+            Command::ExecutableSequence(sequence) => {
+                stack.push(StackValue::ExecutableSequence(sequence.clone()));
+                Rule::Seq
+            }
+            Command::BuiltIn(builtin) => match rewrite(builtin, &mut stack, &mut commands) {
+                Ok(rule) => rule,
+                Err(error) => return Some(Err(error)),
+            },
+        };
+        Some(Ok((rule, Configuration { commands, stack })))
+    }
+
+    /// Every step from this configuration on, ending with
+    /// `Error::Interrupted` as soon as `interrupt` is triggered, or with
+    /// `Error::ResourceExhausted` after the steps `limits` allows.
+    pub fn steps(&self, interrupt: &Interrupt, limits: Limits) -> Steps {
+        Steps {
+            current: Some(self.clone()),
+            interrupt: interrupt.clone(),
+            limit: limits.steps,
+            taken: 0,
+        }
+    }
+}
+
+/// Written `⟨Q, S⟩`, with the stack top first.
+impl fmt::Display for Configuration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "⟨{}, {}⟩", commands(&self.commands), stack(&self.stack))
+    }
+}
+
+fn commands(commands: &[Command]) -> String {
+    format!("{}", Command::ExecutableSequence(commands.to_vec()))
+}
+
+fn pop(stack: &mut Vec<StackValue>) -> Result<StackValue, Error> {
+    stack.pop().ok_or(Error::NotEnoughValues)
+}
+
+/// Apply the rule for `builtin` to `stack` and the `commands` after
+/// it, giving the rule's name.
+fn rewrite(
+    builtin: &BuiltIn,
+    stack: &mut Vec<StackValue>,
+    commands: &mut Vec<Command>,
+) -> Result<Rule, Error> {
+    use crate::read::BuiltIn::*;
+    match builtin {
+        Add | Sub | Mul | Div | Rem => {
+            let n1 = pop(stack)?.into_integer()?;
+            let n2 = pop(stack)?.into_integer()?;
+            let op = match builtin {
+                Add => integer::add,
+                Sub => integer::sub,
+                Mul => integer::mul,
+                Div => integer::div,
+                _ => integer::rem,
+            };
+            if matches!(builtin, Div | Rem) && integer::is_zero(&n1) {
+                return Err(Error::DivideByZero);
+            }
+            stack.push(StackValue::Integer(op(&n2, &n1).ok_or(Error::Overflow)?));
+            Ok(Rule::Arithop)
+        }
+        Eq | Gt | Lt => {
+            let n1 = pop(stack)?.into_integer()?;
+            let n2 = pop(stack)?.into_integer()?;
+            let holds = match builtin {
+                Eq => n2 == n1,
+                Gt => n2 > n1,
+                _ => n2 < n1,
+            };
+            let truth = if holds { 1 } else { 0 };
+            stack.push(StackValue::Integer(Integer::from(truth)));
+            Ok(if holds {
+                Rule::RelopTrue
+            } else {
+                Rule::RelopFalse
+            })
+        }
+        Pop => {
+            pop(stack)?;
+            Ok(Rule::Pop)
+        }
+        Swap => {
+            let v1 = pop(stack)?;
+            let v2 = pop(stack)?;
+            stack.push(v1);
+            stack.push(v2);
+            Ok(Rule::Swap)
+        }
+        Sel => {
+            let v1 = pop(stack)?;
+            let v2 = pop(stack)?;
+            if integer::is_zero(&pop(stack)?.into_integer()?) {
+                stack.push(v1);
+                Ok(Rule::SelFalse)
+            } else {
+                stack.push(v2);
+                Ok(Rule::SelTrue)
+            }
+        }
+        Nget => {
+            let vindex = pop(stack)?.into_integer()?;
+            let len = stack.len();
+            let value = integer::to_index(&vindex)
+                .filter(|&index| index >= 1 && index <= len)
+                .map(|index| stack[len - index].clone())
+                .ok_or(Error::IndexOutOfRange(vindex))?;
+            value.assert_integer()?;
+            stack.push(value);
+            Ok(Rule::Nget)
+        }
+        Exec => {
+            let mut sequence = pop(stack)?.into_ex_seq()?;
+            sequence.append(commands);
+            *commands = sequence;
+            Ok(Rule::Execute)
+        }
+    }
+}
+
+/// The steps from a configuration: the rule each used and the
+/// configuration it gave, ending at a final configuration or with the
+/// error of a stuck one.
+pub struct Steps {
+    current: Option<Configuration>,
+    interrupt: Interrupt,
+    limit: Option<u64>,
+    taken: u64,
+}
+
+impl Iterator for Steps {
+    type Item = Result<(Rule, Configuration), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = self.current.take()?;
+        if current.is_final() {
+            return None;
+        }
+        if self.interrupt.is_triggered() {
+            return Some(Err(Error::Interrupted));
+        }
+        if let Some(limit) = self.limit {
+            if self.taken >= limit {
+                return Some(Err(Error::ResourceExhausted(Limit::Steps(limit))));
+            }
+        }
+        self.taken += 1;
+        let step = current.step()?;
+        if let Ok((_, next)) = &step {
+            self.current = Some(next.clone());
+        }
+        Some(step)
+    }
+}
+
+/// How to write a derivation out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Markdown,
+    Latex,
+}
+
+/// A whole run of a program as a table of steps.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Derivation {
+    pub start: Configuration,
+    pub steps: Vec<(Rule, Configuration)>,
+    /// The program's answer, or the error it got stuck with.
+    pub end: Result<Integer, TopLevelError>,
+}
+
+impl Derivation {
+    /// Run `program` on `args` a step at a time. Being interrupted or
+    /// going past `limits` is an error, not a stuck configuration.
+    pub fn new(
+        program: &Program,
+        args: Vec<Integer>,
+        interrupt: &Interrupt,
+        limits: Limits,
+    ) -> Result<Derivation, TopLevelError> {
+        let start = Configuration::initial(program, args)?;
+        let mut steps = vec![];
+        let mut stuck = None;
+        for step in start.steps(interrupt, limits) {
+            match step {
+                Ok(step) => steps.push(step),
+                Err(error @ (Error::Interrupted | Error::ResourceExhausted(_))) => {
+                    return Err(error.into())
+                }
+                Err(error) => stuck = Some(error),
+            }
+        }
+        let end = match stuck {
+            Some(error) => Err(error.into()),
+            None => steps.last().map_or(&start, |(_, last)| last).answer(),
+        };
+        Ok(Derivation { start, steps, end })
+    }
+
+    fn rows(&self) -> Vec<[String; 4]> {
+        let mut rows = vec![[
+            "0".to_string(),
+            String::new(),
+            commands(&self.start.commands),
+            stack(&self.start.stack),
+        ]];
+        for (number, (rule, configuration)) in self.steps.iter().enumerate() {
+            rows.push([
+                (number + 1).to_string(),
+                rule.to_string(),
+                commands(&configuration.commands),
+                stack(&configuration.stack),
+            ]);
+        }
+        rows
+    }
+
+    fn outcome(&self) -> String {
+        match &self.end {
+            Ok(answer) => format!("answer: {}", answer),
+            Err(error) => format!("stuck: {}", error),
+        }
+    }
+
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Markdown => self.markdown(),
+            Format::Latex => self.latex(),
+        }
+    }
+
+    /// The steps as a Markdown table, then the answer.
+    pub fn markdown(&self) -> String {
+        let mut out = String::from("| Step | Rule | Commands | Stack |\n|---:|---|---|---|\n");
+        for [number, rule, commands, stack] in self.rows() {
+            let rule = if rule.is_empty() {
+                rule
+            } else {
+                format!("`{}`", rule)
+            };
+            out.push_str(&format!(
+                "| {} | {} | `{}` | `{}` |\n",
+                number, rule, commands, stack
+            ));
+        }
+        out.push('\n');
+        out.push_str(&self.outcome());
+        out
+    }
+
+    /// The steps as a LaTeX `tabular`, then the answer.
+    pub fn latex(&self) -> String {
+        let mut out =
+            String::from("\\begin{tabular}{rlll}\nStep & Rule & Commands & Stack \\\\\n\\hline\n");
+        for [number, rule, commands, stack] in self.rows() {
+            out.push_str(&format!(
+                "{} & {} & \\texttt{{{}}} & \\texttt{{{}}} \\\\\n",
+                number,
+                latex_escape(&rule),
+                latex_escape(&commands),
+                latex_escape(&stack)
+            ));
+        }
+        out.push_str("\\end{tabular}\n\n");
+        out.push_str(&latex_escape(&self.outcome()));
+        out
+    }
+}
+
+fn latex_escape(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\textbackslash{}"),
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '~' => out.push_str("\\textasciitilde{}"),
+            '^' => out.push_str("\\textasciicircum{}"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use proptest::prelude::*;
+
+    use crate::generate;
+    use crate::integer::int;
+
+    fn program(num_args: usize, text: &str) -> Program {
+        let sexp = dcpl::SExpParser::parse_line(text).unwrap();
+        match Command::read(sexp).unwrap() {
+            Command::ExecutableSequence(commands) => Program::new(num_args, commands),
+            _ => panic!("expected a sequence"),
+        }
+    }
+
+    fn derive(num_args: usize, text: &str, args: Vec<i128>) -> Derivation {
+        let args = args.into_iter().map(int).collect();
+        let program = program(num_args, text);
+        Derivation::new(&program, args, &Interrupt::new(), Limits::unlimited()).unwrap()
+    }
+
+    fn rules(derivation: &Derivation) -> Vec<&'static str> {
+        derivation
+            .steps
+            .iter()
+            .map(|(rule, _)| rule.name())
+            .collect()
+    }
+
+    #[test]
+    fn test_rules() {
+        let derivation = derive(1, "(1 nget (2 mul) (3 add) sel exec)", vec![5]);
+        assert_eq!(
+            vec!["num", "nget", "seq", "seq", "sel-true", "execute", "num", "arithop"],
+            rules(&derivation)
+        );
+        assert_eq!(Ok(int(10)), derivation.end);
+        let derivation = derive(2, "(lt 0 eq)", vec![1, 2]);
+        assert_eq!(vec!["relop-false", "num", "relop-true"], rules(&derivation));
+    }
+
+    #[test]
+    fn test_execute_prepends_sequence() {
+        let start = Configuration::initial(&program(0, "((1 2) exec add)"), vec![]).unwrap();
+        let configurations: Vec<String> = start
+            .steps(&Interrupt::new(), Limits::unlimited())
+            .map(|step| step.unwrap().1.to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                "⟨(exec add), [(1 2)]⟩",
+                "⟨(1 2 add), []⟩",
+                "⟨(2 add), [1]⟩",
+                "⟨(add), [2, 1]⟩",
+                "⟨(), [3]⟩"
+            ],
+            configurations
+        );
+    }
+
+    #[test]
+    fn test_stuck() {
+        let derivation = derive(0, "(1 swap 2)", vec![]);
+        assert_eq!(vec!["num"], rules(&derivation));
+        assert_eq!(Err(Error::NotEnoughValues.into()), derivation.end);
+        assert!(derivation
+            .markdown()
+            .ends_with("stuck: not enough values on the stack"));
+        let derivation = derive(0, "((1))", vec![]);
+        assert_eq!(Err(TopLevelError::FinalValueNotAnInteger), derivation.end);
+        assert!(derivation
+            .markdown()
+            .ends_with("stuck: the final value isn't an integer"));
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let program = program(0, "((1 2) exec add)");
+        let limits = Limits {
+            steps: Some(4),
+            depth: None,
+        };
+        let expected = Err(Error::ResourceExhausted(Limit::Steps(4)).into());
+        let derivation = Derivation::new(&program, vec![], &Interrupt::new(), limits);
+        assert_eq!(expected, derivation.and_then(|derivation| derivation.end));
+        assert_eq!(
+            expected,
+            program.apply_with_limits(vec![], &Interrupt::new(), limits)
+        );
+        let limits = Limits {
+            steps: Some(5),
+            depth: None,
+        };
+        let derivation = Derivation::new(&program, vec![], &Interrupt::new(), limits).unwrap();
+        assert_eq!(Ok(int(3)), derivation.end);
+    }
+
+    #[test]
+    fn test_interrupted() {
+        let interrupt = Interrupt::new();
+        interrupt.trigger();
+        assert_eq!(
+            Err(Error::Interrupted.into()),
+            Derivation::new(&program(0, "(1)"), vec![], &interrupt, Limits::unlimited())
+        );
+    }
+
+    #[test]
+    fn test_markdown() {
+        assert_eq!(
+            "| Step | Rule | Commands | Stack |\n\
+             |---:|---|---|---|\n\
+             | 0 |  | `(1 nget mul)` | `[5]` |\n\
+             | 1 | `[num]` | `(nget mul)` | `[1, 5]` |\n\
+             | 2 | `[nget]` | `(mul)` | `[5, 5]` |\n\
+             | 3 | `[arithop]` | `()` | `[25]` |\n\
+             \n\
+             answer: 25",
+            derive(1, "(1 nget mul)", vec![5]).markdown()
+        );
+    }
+
+    #[test]
+    fn test_latex() {
+        assert_eq!(
+            "\\begin{tabular}{rlll}\n\
+             Step & Rule & Commands & Stack \\\\\n\
+             \\hline\n\
+             0 &  & \\texttt{(pop)} & \\texttt{[5]} \\\\\n\
+             1 & [pop] & \\texttt{()} & \\texttt{[]} \\\\\n\
+             \\end{tabular}\n\
+             \n\
+             stuck: not enough values on the stack",
+            derive(1, "(pop)", vec![5]).latex()
+        );
+        assert_eq!("100\\% \\_", latex_escape("100% _"));
+    }
+
+    proptest! {
+        #[test]
+        fn agrees_with_apply((program, args) in generate::program()) {
+            let expected = program.apply_with_limits(args.clone(), &Interrupt::new(), Limits::unlimited());
+            let derivation = Derivation::new(&program, args, &Interrupt::new(), Limits::unlimited()).unwrap();
+            prop_assert_eq!(expected, derivation.end);
+        }
+    }
+}
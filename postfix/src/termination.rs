@@ -0,0 +1,180 @@
+//! DCPL's proof that postfix programs terminate, as a computation. It
+//! gives every configuration of a running program (the commands left
+//! to run and the stack) an energy:
+//!
+//! - a sequence command `(Q)` has one more than the commands in `Q`,
+//!   and any other command has 1;
+//! - a sequence on the stack has the energy of its commands, and an
+//!   integer has none.
+//!
+//! Every command that runs lowers the energy by at least one: it leaves
+//! the commands to run, and nothing it leaves on the stack or queues
+//! has more energy than it and the values it pops had. `exec` moves a
+//! sequence's commands from the stack to the commands to run, and is
+//! itself gone. So a program runs at most as many commands as its
+//! energy when it starts.
+//!
+//! The argument would fail if a sequence could be copied, since then
+//! `exec`ing the copies could give back energy forever. `nget` only
+//! copies integers, so it holds for every program, and the bound here
+//! never needs to give up.
+
+use std::fmt;
+
+use crate::program::{Program, StackValue};
+use crate::read::Command;
+
+/// What the proof says about a program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Termination {
+    /// The energy of the program when it starts, which is also the
+    /// most commands a call can run.
+    pub energy: u64,
+}
+
+impl fmt::Display for Termination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        writeln!(f, "energy: {}", self.energy)?;
+        write!(f, "terminates after at most {} commands", self.energy)
+    }
+}
+
+/// The energy of running `command`.
+pub fn command_energy(command: &Command) -> u64 {
+    match command {
+        Command::ExecutableSequence(commands) => 1 + sequence_energy(commands),
+        _ => 1,
+    }
+}
+
+/// The energy of running each of `commands`.
+pub fn sequence_energy<'a>(commands: impl IntoIterator<Item = &'a Command>) -> u64 {
+    commands.into_iter().map(command_energy).sum()
+}
+
+/// The energy of `value` on the stack.
+pub fn value_energy(value: &StackValue) -> u64 {
+    match value {
+        StackValue::ExecutableSequence(commands) => sequence_energy(commands),
+        StackValue::Integer(_) => 0,
+    }
+}
+
+/// The energy of a running program with `commands` left to run and
+/// `stack`.
+pub fn energy<'a>(commands: impl IntoIterator<Item = &'a Command>, stack: &[StackValue]) -> u64 {
+    sequence_energy(commands) + stack.iter().map(value_energy).sum::<u64>()
+}
+
+/// Apply the proof to `program`. Its arguments are integers, with no
+/// energy, so its energy is that of its commands, and no call runs
+/// more commands than that on any arguments. There is never a path
+/// that may loop to report: only `exec` adds commands to run, and it
+/// takes them from a sequence on the stack that it uses up, and since
+/// `nget` never copies a sequence nothing can put that energy back.
+pub fn analyze(program: &Program) -> Termination {
+    Termination {
+        energy: sequence_energy(program.commands()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use dcpl::Interrupt;
+    use proptest::prelude::*;
+
+    use crate::generate;
+    use crate::integer::int;
+    use crate::machine::Machine;
+    use crate::program::{Limits, Stack};
+
+    fn program(num_args: usize, text: &str) -> Program {
+        let sexp = dcpl::SExpParser::parse_line(text).unwrap();
+        match Command::read(sexp).unwrap() {
+            Command::ExecutableSequence(commands) => Program::new(num_args, commands),
+            _ => panic!("expected a sequence"),
+        }
+    }
+
+    fn steps(program: &Program, args: Vec<i128>) -> u64 {
+        let args = args.into_iter().map(int).collect();
+        let limits = Limits::unlimited();
+        let mut machine = Machine::new(program, args, Interrupt::new(), limits).unwrap();
+        let mut steps = 0;
+        while !machine.is_finished() {
+            machine.step().unwrap();
+            steps += 1;
+        }
+        steps
+    }
+
+    #[test]
+    fn test_energy() {
+        let twice = program(1, "((2 mul) exec)");
+        assert_eq!(4, analyze(&twice).energy);
+        assert_eq!(4, steps(&twice, vec![7]));
+        let value = StackValue::from(vec![Command::from(2), Command::from(3)]);
+        assert_eq!(2, value_energy(&value));
+        assert_eq!(0, value_energy(&StackValue::from(2)));
+        assert_eq!(3, energy(twice.commands().iter().skip(1), &[value]));
+    }
+
+    #[test]
+    fn test_analyze() {
+        let termination = analyze(&program(1, "((2 mul) exec)"));
+        assert_eq!(4, termination.energy);
+        assert_eq!(
+            "energy: 4\nterminates after at most 4 commands",
+            termination.to_string()
+        );
+    }
+
+    #[test]
+    fn test_sel_runs_fewer_steps_than_bound() {
+        let choose = program(1, "(1 nget (2 mul) (3 4 5 add add) sel exec)");
+        assert_eq!(13, analyze(&choose).energy);
+        assert_eq!(8, steps(&choose, vec![1]));
+        assert_eq!(11, steps(&choose, vec![0]));
+    }
+
+    proptest! {
+        #[test]
+        fn energy_decreases_each_step((program, args) in generate::program()) {
+            let limits = Limits::unlimited();
+            let mut machine = Machine::new(&program, args, Interrupt::new(), limits).unwrap();
+            let mut before = energy(machine.pending(), machine.stack());
+            prop_assert_eq!(analyze(&program).energy, before);
+            while !machine.is_finished() {
+                if machine.step().is_err() {
+                    break;
+                }
+                let after = energy(machine.pending(), machine.stack());
+                prop_assert!(after < before, "{} is not less than {}", after, before);
+                before = after;
+            }
+        }
+
+        #[test]
+        fn energy_decreases_across_apply_command(
+            command in generate::command(),
+            stack in proptest::collection::vec(generate::command(), 0..5),
+        ) {
+            // `exec` is run by the machine, and checked above.
+            prop_assume!(command != Command::BuiltIn(crate::read::BuiltIn::Exec));
+            let stack: Vec<StackValue> = stack
+                .into_iter()
+                .map(|command| match command {
+                    Command::Integer(value) => StackValue::Integer(value),
+                    Command::ExecutableSequence(commands) => StackValue::ExecutableSequence(commands),
+                    Command::BuiltIn(_) => StackValue::from(1),
+                })
+                .collect();
+            let before = energy(Some(&command), &stack);
+            if let Ok(after) = Program::apply_command(Stack(stack), &command) {
+                prop_assert!(energy(None::<&Command>, &after.0) < before);
+            }
+        }
+    }
+}
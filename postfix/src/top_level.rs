@@ -1,12 +1,63 @@
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
 
-use dcpl::SExp;
+use dcpl::{Interrupt, Language, SExp};
 
-use crate::program::{Error as ProgramError, Program};
+use crate::check::{self, Analysis};
+use crate::debugger::{Breakpoint, Debugger, Pause, Step};
+use crate::integer::{self, Integer};
+use crate::machine::Machine;
+use crate::program::{Error as ProgramError, Limits, Program, StackValue};
 use crate::read::{BuiltIn, Command, Error as ParseError};
+use crate::sos::{Derivation, Format};
+use crate::termination::{self, Termination};
+use crate::trace::{self, TraceEvent};
 
 pub struct TopLevel {
     programs: HashMap<String, Program>,
+    interrupt: Interrupt,
+    limits: Limits,
+    tracing: bool,
+    debugger: Debugger,
+    /// What the check found wrong with the last program defined.
+    warnings: Vec<String>,
+}
+
+/// What evaluating a top-level form produces.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    SExp(SExp),
+    /// The result of a program called with tracing on, along with the
+    /// commands it ran.
+    Traced(Vec<TraceEvent>, SExp),
+    /// Where the program being debugged stopped.
+    Paused(Pause),
+    /// What the static check found about a program.
+    Checked(Analysis),
+    /// A call run by DCPL's rewrite rules, to be written out as a table.
+    Derivation(Derivation, Format),
+    /// What DCPL's termination proof says about a program.
+    Termination(Termination),
+}
+
+impl From<SExp> for Value {
+    fn from(sexp: SExp) -> Value {
+        Value::SExp(sexp)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Value::SExp(sexp) => write!(f, "{}", sexp),
+            Value::Traced(events, result) => write!(f, "{}{}", trace::table(events), result),
+            Value::Paused(pause) => write!(f, "{}", pause),
+            Value::Checked(analysis) => write!(f, "{}", analysis),
+            Value::Derivation(derivation, format) => write!(f, "{}", derivation.render(*format)),
+            Value::Termination(termination) => write!(f, "{}", termination),
+        }
+    }
 }
 
 macro_rules! builtin_program {
@@ -18,6 +69,12 @@ macro_rules! builtin_program {
     };
 }
 
+impl Default for TopLevel {
+    fn default() -> TopLevel {
+        TopLevel::new()
+    }
+}
+
 impl TopLevel {
     pub fn new() -> TopLevel {
         let mut programs = HashMap::new();
@@ -28,24 +85,142 @@ impl TopLevel {
         builtin_program!(programs["eq"] = BuiltIn::Eq : 2);
         builtin_program!(programs["lt"] = BuiltIn::Lt : 2);
         builtin_program!(programs["gt"] = BuiltIn::Gt : 2);
-        TopLevel { programs }
+        TopLevel {
+            programs,
+            interrupt: Interrupt::new(),
+            limits: Limits::default(),
+            tracing: false,
+            debugger: Debugger::new(),
+            warnings: vec![],
+        }
     }
 
-    pub fn interpret(&mut self, sexp: SExp) -> Option<String> {
-        match sexp {
-            SExp::List(exprs) => match TopLevelCommand::read(exprs) {
-                Ok(cmd) => match self.apply(cmd) {
-                    Ok(result) => result,
-                    Err(e) => Some(format!("error: {:?}", e)),
-                },
-                Err(e) => Some(format!("Error: {:?}", e)),
-            },
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Set the limits programs called from now on run with.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Whether calls print a table of the commands they run.
+    pub fn tracing(&self) -> bool {
+        self.tracing
+    }
+
+    pub fn set_tracing(&mut self, tracing: bool) {
+        self.tracing = tracing;
+    }
+
+    fn describe_limits(&self) -> SExp {
+        fn limit(value: Option<impl Into<i128>>) -> SExp {
+            value.map_or(SExp::symbol("none"), |value| SExp::Integer(value.into()))
+        }
+        SExp::List(vec![
+            SExp::symbol("steps"),
+            limit(self.limits.steps),
+            SExp::symbol("depth"),
+            limit(self.limits.depth.map(|depth| depth as u64)),
+        ])
+    }
+
+    /// List the defined programs, one per line, sorted by name.
+    pub fn describe_programs(&self) -> String {
+        let mut names: Vec<&String> = self.programs.keys().collect();
+        names.sort();
+        names
+            .into_iter()
+            .map(|name| format!("{} ({} args)", name, self.programs[name].num_args()))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// Where the program being debugged is paused, as a value.
+    fn lookup(&self, name: &str) -> Result<&Program, Error> {
+        self.programs
+            .get(name)
+            .ok_or_else(|| Error::ProgramNotFound(name.to_string()))
+    }
+
+    fn paused(&self) -> Result<Option<Value>, Error> {
+        let pause = self.debugger.pause().ok_or(Error::NotDebugging)?;
+        Ok(Some(Value::Paused(pause)))
+    }
 
-            expr => Some(format!("{}", expr)),
+    /// The value for a program being debugged that was run some more:
+    /// its result if it finished, or where it stopped.
+    fn resumed(&self, result: Option<Integer>) -> Result<Option<Value>, Error> {
+        match result {
+            Some(result) => Ok(Some(integer::to_sexp(result).into())),
+            None => self.paused(),
         }
     }
 
-    fn apply(&mut self, command: TopLevelCommand) -> Result<Option<String>, Error> {
+    fn debug(&mut self, command: DebugCommand) -> Result<Option<Value>, Error> {
+        use self::DebugCommand::*;
+        match command {
+            Show => self.paused(),
+            Start { name, args } => {
+                let program = self.lookup(&name)?;
+                let machine = Machine::new(program, args, self.interrupt.clone(), self.limits)?;
+                match self.debugger.start(machine) {
+                    Some(result) => self.resumed(Some(result?)),
+                    None => self.paused(),
+                }
+            }
+            Resume(step) => {
+                let result = self.debugger.resume(step)?;
+                self.resumed(result)
+            }
+            Break(None) => {
+                let breakpoints = self.debugger.breakpoints();
+                Ok(Some(
+                    SExp::List(breakpoints.iter().map(Breakpoint::to_sexp).collect()).into(),
+                ))
+            }
+            Break(Some(breakpoint)) => {
+                self.debugger.add_breakpoint(breakpoint);
+                Ok(None)
+            }
+            Delete(breakpoint) => {
+                if self.debugger.remove_breakpoint(&breakpoint) {
+                    Ok(None)
+                } else {
+                    Err(Error::BreakpointNotFound(breakpoint.to_string()))
+                }
+            }
+            Stack(None) => {
+                let machine = self.debugger.machine().ok_or(Error::NotDebugging)?;
+                let values = machine.stack().iter().rev().map(stack_value_to_sexp);
+                Ok(Some(SExp::List(values.collect()).into()))
+            }
+            Stack(Some(values)) => {
+                let machine = self.debugger.machine_mut().ok_or(Error::NotDebugging)?;
+                *machine.stack_mut() = values.into_iter().rev().collect();
+                self.paused()
+            }
+            Push(value) => {
+                let machine = self.debugger.machine_mut().ok_or(Error::NotDebugging)?;
+                machine.stack_mut().push(value);
+                self.paused()
+            }
+            Pop => {
+                let machine = self.debugger.machine_mut().ok_or(Error::NotDebugging)?;
+                machine
+                    .stack_mut()
+                    .pop()
+                    .ok_or(ProgramError::NotEnoughValues)?;
+                self.paused()
+            }
+            Quit => {
+                self.debugger.quit();
+                Ok(None)
+            }
+        }
+    }
+
+    fn apply(&mut self, command: TopLevelCommand) -> Result<Option<Value>, Error> {
         use self::TopLevelCommand::*;
         match command {
             Def {
@@ -54,21 +229,218 @@ impl TopLevel {
                 commands,
             } => {
                 let program = Program::new(num_args, commands);
+                if let Some((command, error)) = check::check(&program).failure() {
+                    let place = match command {
+                        Some(command) => format!("at {}", command),
+                        None => "at the end".to_string(),
+                    };
+                    let warning = format!("{} always fails {}: {:?}", name, place, error);
+                    self.warnings.push(warning);
+                }
                 self.programs.insert(name, program);
                 Ok(None)
             }
             Call { name, args } => {
-                let program = self
-                    .programs
-                    .get(&name)
-                    .ok_or_else(|| Error::ProgramNotFound(name))?;
-                let result = program.apply(args)?;
-                Ok(Some(format!("{}", result)))
+                let program = self.lookup(&name)?;
+                if self.tracing {
+                    let (events, result) = program.trace(args, &self.interrupt, self.limits);
+                    let result = integer::to_sexp(result?);
+                    return Ok(Some(Value::Traced(events, result)));
+                }
+                let result = program.apply_with_limits(args, &self.interrupt, self.limits)?;
+                Ok(Some(integer::to_sexp(result).into()))
+            }
+            Limit(None) => Ok(Some(self.describe_limits().into())),
+            Limit(Some(Setting::Steps(steps))) => {
+                self.limits.steps = steps;
+                Ok(None)
+            }
+            Limit(Some(Setting::Depth(depth))) => {
+                self.limits.depth = depth;
+                Ok(None)
+            }
+            Trace(None) => {
+                let state = if self.tracing { "on" } else { "off" };
+                Ok(Some(SExp::symbol(state).into()))
+            }
+            Trace(Some(tracing)) => {
+                self.tracing = tracing;
+                Ok(None)
+            }
+            Debug(command) => self.debug(command),
+            Check(name) => {
+                let program = self.lookup(&name)?;
+                Ok(Some(Value::Checked(check::check(program))))
+            }
+            Sos { format, name, args } => {
+                let program = self.lookup(&name)?;
+                let derivation = Derivation::new(program, args, &self.interrupt, self.limits)?;
+                Ok(Some(Value::Derivation(derivation, format)))
+            }
+            Energy(name) => {
+                let program = self.lookup(&name)?;
+                Ok(Some(Value::Termination(termination::analyze(program))))
+            }
+        }
+    }
+}
+
+impl Language for TopLevel {
+    type Value = Value;
+    type Error = Error;
+
+    fn name(&self) -> &str {
+        "Postfix"
+    }
+
+    fn eval(&mut self, expr: SExp) -> Result<Option<Value>, Error> {
+        self.warnings.clear();
+        match expr {
+            SExp::List(exprs) => {
+                let command = TopLevelCommand::read(exprs)?;
+                self.apply(command)
             }
+
+            expr => Ok(Some(expr.into())),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.programs = TopLevel::new().programs;
+        self.debugger.quit();
+    }
+
+    fn set_interrupt(&mut self, interrupt: Interrupt) {
+        self.interrupt = interrupt;
+    }
+
+    fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    fn save(&self) -> Vec<SExp> {
+        let initial = TopLevel::new();
+        let mut names: Vec<&String> = self
+            .programs
+            .iter()
+            .filter(|(name, program)| initial.programs.get(*name) != Some(program))
+            .map(|(name, _)| name)
+            .collect();
+        names.sort();
+        names
+            .into_iter()
+            .map(|name| {
+                let program = &self.programs[name];
+                let mut def = vec![
+                    SExp::symbol("def"),
+                    SExp::symbol(name.as_str()),
+                    SExp::Integer(program.num_args() as i128),
+                ];
+                def.extend(program.commands().iter().map(Command::to_sexp));
+                SExp::List(def)
+            })
+            .collect()
+    }
+
+    fn complete(&self, prefix: &str) -> Vec<String> {
+        let mut names: Vec<String> = BuiltIn::ALL
+            .iter()
+            .map(|builtin| builtin.name().to_string())
+            .chain(self.programs.keys().cloned())
+            .chain(vec![
+                "check".to_string(),
+                "debug".to_string(),
+                "energy".to_string(),
+                "sos".to_string(),
+                "def".to_string(),
+                "limit".to_string(),
+                "trace".to_string(),
+            ])
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    fn describe(&self, name: &str) -> Option<String> {
+        if name == "def" {
+            return Some("(def name num-args commands...): define a program".into());
+        }
+        if name == "check" {
+            return Some(
+                "(check program): show what is known of the stack after each command \
+                 without running it"
+                    .into(),
+            );
+        }
+        if name == "sos" {
+            return Some(
+                "(sos [markdown|latex] (program args...)): run a call by DCPL's rewrite \
+                 rules and show each step as a Markdown or LaTeX table"
+                    .into(),
+            );
+        }
+        if name == "energy" {
+            return Some(
+                "(energy program): the energy of a program, which bounds how many \
+                 commands it runs on any arguments"
+                    .into(),
+            );
+        }
+        if name == "debug" {
+            return Some(
+                "(debug (program args...)): debug a call, paused before its first command; \
+                 (debug step|next|out|continue): run into or over the next command, \
+                 out of the current exec, or to the end or a breakpoint; \
+                 (debug break [index|builtin]), (debug delete index|builtin): \
+                 set, list or delete breakpoints; \
+                 (debug stack [values...]), (debug push value), (debug pop): \
+                 show or change the stack, top first; (debug quit): stop debugging"
+                    .into(),
+            );
+        }
+        if name == "trace" {
+            return Some(
+                "(trace on|off): show a table of the commands each call runs; \
+                 (trace) shows whether tracing is on"
+                    .into(),
+            );
+        }
+        if name == "limit" {
+            return Some(
+                "(limit steps|depth n|none): limit how many commands programs run, \
+                 or how deeply their execs nest; (limit) shows the limits"
+                    .into(),
+            );
+        }
+        if let Ok(builtin) = BuiltIn::read(name.into()) {
+            return Some(builtin.doc().into());
+        }
+        self.programs.get(name).map(|program| {
+            let mut definition = format!("(def {} {}", name, program.num_args());
+            for command in program.commands() {
+                definition.push_str(&format!(" {}", command));
+            }
+            definition.push(')');
+            definition
+        })
+    }
+
+    fn defines(&self, form: &SExp) -> Option<String> {
+        match form {
+            SExp::List(exprs) => match exprs.as_slice() {
+                [SExp::Symbol(def), SExp::Symbol(name), ..] if def == "def" => Some(name.clone()),
+                _ => None,
+            },
+            _ => None,
         }
     }
 }
 
+// `ReadError` and `ProgramError` name the errors they wrap, and they
+// appear in error output, so they keep their names.
+#[allow(clippy::enum_variant_names)]
 #[derive(Clone, Debug, PartialEq)]
 pub enum Error {
     IllegalArgumentType(SExp),
@@ -76,8 +448,17 @@ pub enum Error {
     NotASymbol,
     NotAnInteger,
     NotEnoughArgs(&'static str),
+    UnknownLimit(String),
+    UnknownDebugCommand(String),
+    NotDebugging,
+    BreakpointNotFound(String),
     ProgramNotFound(String),
-    WrongNumberOfArgs { expected: usize, actual: usize },
+    /// A `def` of a name the top level uses for something else.
+    ReservedName(String),
+    WrongNumberOfArgs {
+        expected: usize,
+        actual: usize,
+    },
     ReadError(ParseError),
     ProgramError(ProgramError),
 }
@@ -88,6 +469,30 @@ impl Error {
     }
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        use self::Error::*;
+        match self {
+            IllegalArgumentType(expr) => write!(f, "`{}` isn't allowed here", expr),
+            FinalValueNotAnInteger => write!(f, "the final value isn't an integer"),
+            NotASymbol => write!(f, "expected a name"),
+            NotAnInteger => write!(f, "expected an integer"),
+            NotEnoughArgs(what) => write!(f, "not enough arguments to `{}`", what),
+            UnknownLimit(name) => write!(f, "unknown limit `{}`", name),
+            UnknownDebugCommand(name) => write!(f, "unknown debug command `{}`", name),
+            NotDebugging => write!(f, "no call is being debugged"),
+            BreakpointNotFound(breakpoint) => write!(f, "no breakpoint at {}", breakpoint),
+            ProgramNotFound(name) => write!(f, "no program named `{}`", name),
+            ReservedName(name) => write!(f, "`{}` is a top-level command", name),
+            WrongNumberOfArgs { expected, actual } => {
+                write!(f, "expected {} arguments but got {}", expected, actual)
+            }
+            ReadError(error) => write!(f, "{}", error),
+            ProgramError(error) => write!(f, "{}", error),
+        }
+    }
+}
+
 impl From<ParseError> for Error {
     fn from(err: ParseError) -> Error {
         Error::ReadError(err)
@@ -100,6 +505,9 @@ impl From<ProgramError> for Error {
     }
 }
 
+/// The names of the top-level commands, which can't be programs.
+const RESERVED: [&str; 7] = ["def", "limit", "trace", "check", "sos", "energy", "debug"];
+
 #[derive(Clone, Debug, PartialEq)]
 enum TopLevelCommand {
     Def {
@@ -110,16 +518,62 @@ enum TopLevelCommand {
 
     Call {
         name: String,
-        args: Vec<i128>,
+        args: Vec<Integer>,
+    },
+
+    /// Change one of the limits, or show them all.
+    Limit(Option<Setting>),
+
+    /// Turn tracing on or off, or show whether it is on.
+    Trace(Option<bool>),
+
+    Debug(DebugCommand),
+
+    /// Show the static check of a program.
+    Check(String),
+
+    /// Show the energy of a program, the most commands it can run.
+    Energy(String),
+
+    /// Show the rewrite rules a call runs by, as a table.
+    Sos {
+        format: Format,
+        name: String,
+        args: Vec<Integer>,
     },
 }
 
+#[derive(Clone, Debug, PartialEq)]
+enum DebugCommand {
+    /// Show where the program being debugged is paused.
+    Show,
+    Start {
+        name: String,
+        args: Vec<Integer>,
+    },
+    Resume(Step),
+    /// Set a breakpoint, or list them.
+    Break(Option<Breakpoint>),
+    Delete(Breakpoint),
+    /// Replace the stack, or show it. Values are top first.
+    Stack(Option<Vec<StackValue>>),
+    Push(StackValue),
+    Pop,
+    Quit,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Setting {
+    Steps(Option<u64>),
+    Depth(Option<usize>),
+}
+
 impl TopLevelCommand {
     fn read(exprs: impl IntoIterator<Item = SExp>) -> Result<TopLevelCommand, Error> {
         let mut exprs = exprs.into_iter();
         exprs
             .next()
-            .ok_or_else(|| Error::NotEnoughArgs("()"))
+            .ok_or(Error::NotEnoughArgs("()"))
             .and_then(|expr| expr.into_symbol().ok_or(Error::NotASymbol))
             .and_then(|name| TopLevelCommand::read_symbol(&name, exprs))
     }
@@ -130,6 +584,18 @@ impl TopLevelCommand {
     ) -> Result<TopLevelCommand, Error> {
         if name == "def" {
             TopLevelCommand::def(rest)
+        } else if name == "limit" {
+            TopLevelCommand::limit(rest)
+        } else if name == "trace" {
+            TopLevelCommand::trace(rest)
+        } else if name == "check" {
+            TopLevelCommand::program_name("check", rest).map(TopLevelCommand::Check)
+        } else if name == "sos" {
+            TopLevelCommand::sos(rest)
+        } else if name == "energy" {
+            TopLevelCommand::program_name("energy", rest).map(TopLevelCommand::Energy)
+        } else if name == "debug" {
+            TopLevelCommand::debug(rest)
         } else {
             TopLevelCommand::call(name, rest)
         }
@@ -139,12 +605,15 @@ impl TopLevelCommand {
         let mut exprs = exprs.into_iter();
         let name = exprs
             .next()
-            .ok_or_else(|| Error::NotEnoughArgs("def"))?
+            .ok_or(Error::NotEnoughArgs("def"))?
             .into_symbol()
             .ok_or(Error::NotASymbol)?;
+        if RESERVED.contains(&name.as_str()) {
+            return Err(Error::ReservedName(name));
+        }
         let num_args = exprs
             .next()
-            .ok_or_else(|| Error::NotEnoughArgs("def"))?
+            .ok_or(Error::NotEnoughArgs("def"))?
             .into_integer()
             .ok_or(Error::NotAnInteger)? as usize;
         let commands = exprs
@@ -157,24 +626,162 @@ impl TopLevelCommand {
         })
     }
 
+    fn limit(exprs: impl IntoIterator<Item = SExp>) -> Result<TopLevelCommand, Error> {
+        let mut exprs = exprs.into_iter();
+        let name = match exprs.next() {
+            Some(expr) => expr.into_symbol().ok_or(Error::NotASymbol)?,
+            None => return Ok(TopLevelCommand::Limit(None)),
+        };
+        let value = match exprs.next().ok_or(Error::NotEnoughArgs("limit"))? {
+            SExp::Symbol(none) if none == "none" => None,
+            SExp::Integer(value) if value >= 0 && value <= i128::from(u64::MAX) => Some(value),
+            expr => return Err(Error::IllegalArgumentType(expr)),
+        };
+        let setting = match name.as_str() {
+            "steps" => Setting::Steps(value.map(|value| value as u64)),
+            "depth" => Setting::Depth(value.map(|value| value as usize)),
+            _ => return Err(Error::UnknownLimit(name)),
+        };
+        Ok(TopLevelCommand::Limit(Some(setting)))
+    }
+
+    fn trace(exprs: impl IntoIterator<Item = SExp>) -> Result<TopLevelCommand, Error> {
+        let mut exprs = exprs.into_iter();
+        let tracing = match exprs.next() {
+            None => None,
+            Some(SExp::Symbol(state)) if state == "on" => Some(true),
+            Some(SExp::Symbol(state)) if state == "off" => Some(false),
+            Some(expr) => return Err(Error::IllegalArgumentType(expr)),
+        };
+        Ok(TopLevelCommand::Trace(tracing))
+    }
+
+    /// The name of the program a form like `(check name)` is about.
+    fn program_name(
+        form: &'static str,
+        exprs: impl IntoIterator<Item = SExp>,
+    ) -> Result<String, Error> {
+        exprs
+            .into_iter()
+            .next()
+            .ok_or(Error::NotEnoughArgs(form))?
+            .into_symbol()
+            .ok_or(Error::NotASymbol)
+    }
+
+    fn sos(exprs: impl IntoIterator<Item = SExp>) -> Result<TopLevelCommand, Error> {
+        let mut exprs = exprs.into_iter().peekable();
+        let format = match exprs.peek() {
+            Some(SExp::Symbol(format)) if format == "markdown" => Format::Markdown,
+            Some(SExp::Symbol(format)) if format == "latex" => Format::Latex,
+            Some(SExp::List(_)) => Format::Markdown,
+            Some(expr) => return Err(Error::IllegalArgumentType(expr.clone())),
+            None => return Err(Error::NotEnoughArgs("sos")),
+        };
+        if let Some(SExp::Symbol(_)) = exprs.peek() {
+            exprs.next();
+        }
+        match exprs.next() {
+            Some(SExp::List(call)) => match TopLevelCommand::read(call.clone())? {
+                TopLevelCommand::Call { name, args } => {
+                    Ok(TopLevelCommand::Sos { format, name, args })
+                }
+                _ => Err(Error::IllegalArgumentType(SExp::List(call))),
+            },
+            Some(expr) => Err(Error::IllegalArgumentType(expr)),
+            None => Err(Error::NotEnoughArgs("sos")),
+        }
+    }
+
+    fn debug(exprs: impl IntoIterator<Item = SExp>) -> Result<TopLevelCommand, Error> {
+        use self::DebugCommand::*;
+        let mut exprs = exprs.into_iter();
+        let name = match exprs.next() {
+            None => return Ok(TopLevelCommand::Debug(Show)),
+            Some(SExp::List(call)) => {
+                return match TopLevelCommand::read(call.clone())? {
+                    TopLevelCommand::Call { name, args } => {
+                        Ok(TopLevelCommand::Debug(Start { name, args }))
+                    }
+                    _ => Err(Error::IllegalArgumentType(SExp::List(call))),
+                };
+            }
+            Some(expr) => expr.into_symbol().ok_or(Error::NotASymbol)?,
+        };
+        let command = match name.as_str() {
+            "step" => Resume(Step::Into),
+            "next" => Resume(Step::Over),
+            "out" => Resume(Step::Out),
+            "continue" => Resume(Step::Continue),
+            "break" => Break(exprs.next().map(read_breakpoint).transpose()?),
+            "delete" => Delete(read_breakpoint(
+                exprs.next().ok_or(Error::NotEnoughArgs("debug delete"))?,
+            )?),
+            "stack" => {
+                let values = exprs
+                    .map(read_stack_value)
+                    .collect::<Result<Vec<StackValue>, Error>>()?;
+                Stack(if values.is_empty() {
+                    None
+                } else {
+                    Some(values)
+                })
+            }
+            "push" => Push(read_stack_value(
+                exprs.next().ok_or(Error::NotEnoughArgs("debug push"))?,
+            )?),
+            "pop" => Pop,
+            "quit" => Quit,
+            _ => return Err(Error::UnknownDebugCommand(name)),
+        };
+        Ok(TopLevelCommand::Debug(command))
+    }
+
     fn call(name: &str, exprs: impl IntoIterator<Item = SExp>) -> Result<TopLevelCommand, Error> {
         let name = name.to_string();
         let mut args = vec![];
         for expr in exprs {
-            match expr {
-                SExp::Integer(value) => args.push(value),
-                expr => return Err(Error::IllegalArgumentType(expr)),
-            }
+            args.push(integer::from_sexp(expr).map_err(Error::IllegalArgumentType)?);
         }
         Ok(TopLevelCommand::Call { name, args })
     }
 }
 
+fn read_breakpoint(expr: SExp) -> Result<Breakpoint, Error> {
+    match expr {
+        SExp::Integer(index) => usize::try_from(index)
+            .map(Breakpoint::Index)
+            .map_err(|_| Error::IllegalArgumentType(expr)),
+        SExp::Symbol(name) => Ok(Breakpoint::BuiltIn(BuiltIn::read(name)?)),
+        expr => Err(Error::IllegalArgumentType(expr)),
+    }
+}
+
+/// An integer or an executable sequence, written as in a program.
+fn read_stack_value(expr: SExp) -> Result<StackValue, Error> {
+    match Command::read(expr.clone())? {
+        Command::Integer(value) => Ok(StackValue::Integer(value)),
+        Command::ExecutableSequence(commands) => Ok(StackValue::ExecutableSequence(commands)),
+        Command::BuiltIn(_) => Err(Error::IllegalArgumentType(expr)),
+    }
+}
+
+fn stack_value_to_sexp(value: &StackValue) -> SExp {
+    match value {
+        StackValue::Integer(value) => integer::to_sexp(value.clone()),
+        StackValue::ExecutableSequence(commands) => {
+            Command::ExecutableSequence(commands.clone()).to_sexp()
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::TopLevelCommand::*;
     use super::*;
 
+    use crate::integer::int;
+
     use crate::read::BuiltIn::*;
     use crate::read::Command::*;
 
@@ -193,17 +800,301 @@ mod test {
         let expected = Def {
             name: "foo".into(),
             num_args: 2,
-            commands: vec![Integer(4), Integer(7), BuiltIn(Sub)],
+            commands: vec![Integer(int(4)), Integer(int(7)), BuiltIn(Sub)],
         };
         assert_eq!(Ok(expected), read_top_level("(def foo 2 4 7 sub)"))
     }
 
+    #[test]
+    fn test_top_level_def_reserved_name() {
+        for name in RESERVED.iter() {
+            assert_eq!(
+                Err(Error::ReservedName(name.to_string())),
+                read_top_level(format!("(def {} 0 1)", name))
+            );
+        }
+    }
+
     #[test]
     fn test_top_level_read_call() {
         let expected = Call {
             name: "bar".into(),
-            args: vec![1, 2],
+            args: vec![int(1), int(2)],
         };
         assert_eq!(Ok(expected), read_top_level("(bar 1 2)"))
     }
+
+    fn eval(top_level: &mut TopLevel, sexp_str: &str) -> Result<Option<Value>, Error> {
+        top_level.eval(SExpParser::parse_line(sexp_str).expect("unexpected parse error"))
+    }
+
+    #[test]
+    fn test_top_level_def_and_call() {
+        let mut top_level = TopLevel::new();
+        assert_eq!(Ok(None), eval(&mut top_level, "(def foo 1 2 mul)"));
+        assert_eq!(
+            Ok(Some(SExp::Integer(10).into())),
+            eval(&mut top_level, "(foo 5)")
+        );
+    }
+
+    #[test]
+    fn test_top_level_echo_atom() {
+        let mut top_level = TopLevel::new();
+        assert_eq!(Ok(Some(SExp::Integer(3).into())), eval(&mut top_level, "3"));
+    }
+
+    #[test]
+    fn test_top_level_limit() {
+        let mut top_level = TopLevel::new();
+        assert_eq!(
+            Ok(Some(
+                SExpParser::parse_line("(steps none depth 1000)")
+                    .unwrap()
+                    .into()
+            )),
+            eval(&mut top_level, "(limit)")
+        );
+        assert_eq!(Ok(None), eval(&mut top_level, "(limit steps 0)"));
+        assert_eq!(Ok(None), eval(&mut top_level, "(limit depth none)"));
+        assert_eq!(
+            Limits {
+                steps: Some(0),
+                depth: None
+            },
+            top_level.limits()
+        );
+        assert_eq!(
+            Err(Error::ProgramError(ProgramError::ResourceExhausted(
+                crate::program::Limit::Steps(0)
+            ))),
+            eval(&mut top_level, "(add 1 2)")
+        );
+    }
+
+    #[test]
+    fn test_top_level_trace() {
+        let mut top_level = TopLevel::new();
+        assert_eq!(
+            Ok(Some(SExp::symbol("off").into())),
+            eval(&mut top_level, "(trace)")
+        );
+        eval(&mut top_level, "(def sq 1 1 nget mul)").unwrap();
+        assert_eq!(Ok(None), eval(&mut top_level, "(trace on)"));
+        assert!(top_level.tracing());
+        let value = eval(&mut top_level, "(sq 3)").unwrap().unwrap();
+        match &value {
+            Value::Traced(events, result) => {
+                assert_eq!(3, events.len());
+                assert_eq!(&SExp::Integer(9), result);
+            }
+            value => panic!("expected a traced value, got {:?}", value),
+        }
+        assert!(value.to_string().ends_with("mul      [9]\n9"));
+        assert_eq!(
+            Err(Error::IllegalArgumentType(SExp::symbol("yes"))),
+            eval(&mut top_level, "(trace yes)")
+        );
+    }
+
+    #[test]
+    fn test_top_level_read_limit_errors() {
+        assert_eq!(
+            Err(Error::UnknownLimit("time".into())),
+            read_top_level("(limit time 10)")
+        );
+        assert_eq!(
+            Err(Error::NotEnoughArgs("limit")),
+            read_top_level("(limit steps)")
+        );
+        assert_eq!(
+            Err(Error::IllegalArgumentType(SExp::Integer(-1))),
+            read_top_level("(limit depth -1)")
+        );
+    }
+
+    #[test]
+    fn test_top_level_def_warns() {
+        let mut top_level = TopLevel::new();
+        assert_eq!(Ok(None), eval(&mut top_level, "(def sq 1 1 nget mul)"));
+        assert_eq!(Vec::<String>::new(), top_level.take_warnings());
+        assert_eq!(Ok(None), eval(&mut top_level, "(def swap-one 0 1 swap)"));
+        assert_eq!(
+            vec!["swap-one always fails at swap: ProgramError(NotEnoughValues)".to_string()],
+            top_level.take_warnings()
+        );
+        assert_eq!(Vec::<String>::new(), top_level.take_warnings());
+        assert!(top_level.describe("swap-one").is_some());
+        match eval(&mut top_level, "(check sq)") {
+            Ok(Some(Value::Checked(analysis))) => assert_eq!(3, analysis.points.len()),
+            value => panic!("expected a check, got {:?}", value),
+        }
+    }
+
+    #[test]
+    fn test_top_level_read_sos() {
+        let sos = |format| Sos {
+            format,
+            name: "sq".into(),
+            args: vec![int(3)],
+        };
+        assert_eq!(Ok(sos(Format::Markdown)), read_top_level("(sos (sq 3))"));
+        assert_eq!(Ok(sos(Format::Latex)), read_top_level("(sos latex (sq 3))"));
+        assert_eq!(
+            Err(Error::NotEnoughArgs("sos")),
+            read_top_level("(sos markdown)")
+        );
+    }
+
+    #[test]
+    fn test_top_level_read_debug() {
+        assert_eq!(
+            Ok(Debug(DebugCommand::Start {
+                name: "sq".into(),
+                args: vec![int(3)]
+            })),
+            read_top_level("(debug (sq 3))")
+        );
+        assert_eq!(
+            Ok(Debug(DebugCommand::Break(Some(Breakpoint::BuiltIn(Mul))))),
+            read_top_level("(debug break mul)")
+        );
+        assert_eq!(
+            Err(Error::UnknownDebugCommand("jump".into())),
+            read_top_level("(debug jump)")
+        );
+        assert_eq!(
+            Ok(Debug(DebugCommand::Break(Some(Breakpoint::Index(3))))),
+            read_top_level("(debug break 3)")
+        );
+        assert_eq!(
+            Err(Error::IllegalArgumentType(SExp::Integer(-1))),
+            read_top_level("(debug break -1)")
+        );
+        assert_eq!(
+            Err(Error::IllegalArgumentType(SExp::Integer(-1))),
+            read_top_level("(debug delete -1)")
+        );
+        assert_eq!(
+            Err(Error::IllegalArgumentType(SExp::symbol("add"))),
+            read_top_level("(debug push add)")
+        );
+    }
+
+    #[test]
+    fn test_top_level_unknown_program() {
+        let mut top_level = TopLevel::new();
+        assert_eq!(
+            Err(Error::ProgramNotFound("foo".into())),
+            eval(&mut top_level, "(foo 5)")
+        );
+        for form in [
+            "(check foo)",
+            "(energy foo)",
+            "(sos (foo 5))",
+            "(debug (foo 5))",
+        ]
+        .iter()
+        {
+            assert_eq!(
+                Err(Error::ProgramNotFound("foo".into())),
+                eval(&mut top_level, form)
+            );
+        }
+    }
+
+    #[test]
+    fn test_top_level_complete() {
+        let mut top_level = TopLevel::new();
+        eval(&mut top_level, "(def swapper 0 1 2 swap)").unwrap();
+        assert_eq!(
+            vec!["sel", "sos", "sub", "swap", "swapper"],
+            top_level.complete("s")
+        );
+    }
+
+    #[test]
+    fn test_top_level_describe_program() {
+        let mut top_level = TopLevel::new();
+        eval(&mut top_level, "(def foo 1 (2 mul) exec)").unwrap();
+        assert_eq!(
+            Some("(def foo 1 (2 mul) exec)".to_string()),
+            top_level.describe("foo")
+        );
+    }
+
+    #[test]
+    fn test_top_level_reset() {
+        let mut top_level = TopLevel::new();
+        eval(&mut top_level, "(def foo 1 2 mul)").unwrap();
+        assert!(top_level.describe_programs().contains("foo (1 args)"));
+        top_level.reset();
+        assert!(!top_level.describe_programs().contains("foo"));
+    }
+
+    #[test]
+    fn test_top_level_interrupted() {
+        let mut top_level = TopLevel::new();
+        let interrupt = Interrupt::new();
+        top_level.set_interrupt(interrupt.clone());
+        top_level.reset();
+        interrupt.trigger();
+        assert_eq!(
+            Err(Error::ProgramError(ProgramError::Interrupted)),
+            eval(&mut top_level, "(add 1 2)")
+        );
+    }
+
+    #[test]
+    fn test_top_level_save_and_restore() {
+        let mut top_level = TopLevel::new();
+        eval(&mut top_level, "(def sq 1 1 nget mul)").unwrap();
+        eval(&mut top_level, "(def add 2 (add) exec)").unwrap();
+        let image = top_level.save();
+        let texts: Vec<String> = image.iter().map(SExp::to_string).collect();
+        assert_eq!(
+            vec!["(def add 2 (add) exec)", "(def sq 1 1 nget mul)"],
+            texts
+        );
+
+        let mut restored = TopLevel::new();
+        for form in image {
+            restored.eval(form).unwrap();
+        }
+        assert_eq!(
+            Ok(Some(SExp::Integer(16).into())),
+            eval(&mut restored, "(sq 4)")
+        );
+        assert_eq!(top_level.describe("add"), restored.describe("add"));
+    }
+
+    #[test]
+    fn test_transcripts() {
+        dcpl::transcript::assert_transcripts(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/transcripts"),
+            TopLevel::new,
+        );
+        #[cfg(not(feature = "bignum"))]
+        dcpl::transcript::assert_transcripts(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/transcripts/i128"),
+            TopLevel::new,
+        );
+        #[cfg(feature = "bignum")]
+        dcpl::transcript::assert_transcripts(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/transcripts/bignum"),
+            TopLevel::new,
+        );
+    }
+
+    #[test]
+    fn test_defines() {
+        let top_level = TopLevel::new();
+        let form = |text| SExpParser::parse_line(text).unwrap();
+        assert_eq!(
+            Some("sq".to_string()),
+            top_level.defines(&form("(def sq 1 (dup mul))"))
+        );
+        assert_eq!(None, top_level.defines(&form("(sq 3)")));
+        assert_eq!(None, top_level.defines(&form("def")));
+    }
 }
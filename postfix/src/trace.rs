@@ -0,0 +1,146 @@
+//! Traces of the commands a postfix program runs, and the execution
+//! tables DCPL uses to show them.
+
+use crate::program::{Error, StackValue};
+use crate::read::Command;
+
+/// One command run by a traced program.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEvent {
+    pub command: Command,
+    /// The stack before the command ran, bottom first.
+    pub before: Vec<StackValue>,
+    /// The stack the command left, bottom first, or the error it
+    /// failed with. For `exec` this is after the whole sequence ran.
+    pub after: Result<Vec<StackValue>, Error>,
+    /// How many `exec`s the command ran inside of.
+    pub depth: usize,
+}
+
+/// Lay `trace` out as an execution table: the starting stack, then
+/// each command, indented by how deeply it is nested in `exec`s, next
+/// to the stack it left. Stacks are written top first.
+pub fn table(trace: &[TraceEvent]) -> String {
+    let mut rows = vec![("Command".to_string(), "Stack".to_string())];
+    if let Some(first) = trace.first() {
+        rows.push((String::new(), stack(&first.before)));
+    }
+    for event in trace {
+        let command = format!("{}{}", "  ".repeat(event.depth), event.command);
+        let after = match &event.after {
+            Ok(after) => stack(after),
+            Err(error) => format!("error: {:?}", error),
+        };
+        rows.push((command, after));
+    }
+    layout(rows)
+}
+
+/// Lay `rows` out in two columns, the first as wide as its widest entry.
+pub(crate) fn layout(rows: Vec<(String, String)>) -> String {
+    let width = rows
+        .iter()
+        .map(|(command, _)| command.chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut out = String::new();
+    for (command, stack) in rows {
+        let row = format!("{:width$}  {}", command, stack, width = width);
+        out.push_str(row.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// `values`, written top first.
+pub(crate) fn stack(values: &[StackValue]) -> String {
+    let values: Vec<String> = values.iter().rev().map(ToString::to_string).collect();
+    format!("[{}]", values.join(", "))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use dcpl::Interrupt;
+
+    use crate::integer::int;
+    use crate::program::{Limits, Program};
+    use crate::read::BuiltIn;
+
+    #[test]
+    fn test_trace_events() {
+        // (def square 1 1 nget mul)
+        let square = Program::new(
+            1,
+            vec![
+                Command::from(1),
+                Command::BuiltIn(BuiltIn::Nget),
+                Command::BuiltIn(BuiltIn::Mul),
+            ],
+        );
+        let (trace, result) = square.trace(vec![int(5)], &Interrupt::new(), Limits::default());
+        assert_eq!(Ok(int(25)), result);
+        let five = StackValue::from(5);
+        assert_eq!(
+            vec![
+                TraceEvent {
+                    command: Command::from(1),
+                    before: vec![five.clone()],
+                    after: Ok(vec![five.clone(), StackValue::from(1)]),
+                    depth: 0,
+                },
+                TraceEvent {
+                    command: Command::BuiltIn(BuiltIn::Nget),
+                    before: vec![five.clone(), StackValue::from(1)],
+                    after: Ok(vec![five.clone(), five.clone()]),
+                    depth: 0,
+                },
+                TraceEvent {
+                    command: Command::BuiltIn(BuiltIn::Mul),
+                    before: vec![five.clone(), five],
+                    after: Ok(vec![StackValue::from(25)]),
+                    depth: 0,
+                },
+            ],
+            trace
+        );
+    }
+
+    #[test]
+    fn test_table() {
+        // (def twice 1 (2 mul) exec)
+        let twice = Program::new(
+            1,
+            vec![
+                Command::ExecutableSequence(vec![Command::from(2), Command::BuiltIn(BuiltIn::Mul)]),
+                Command::BuiltIn(BuiltIn::Exec),
+            ],
+        );
+        let (trace, _) = twice.trace(vec![int(7)], &Interrupt::new(), Limits::default());
+        assert_eq!(
+            "Command  Stack\n\
+             \x20        [7]\n\
+             (2 mul)  [(2 mul), 7]\n\
+             exec     [14]\n\
+             \x20 2      [2, 7]\n\
+             \x20 mul    [14]\n",
+            table(&trace)
+        );
+    }
+
+    #[test]
+    fn test_trace_ends_at_error() {
+        let program = Program::new(0, vec![Command::from(1), Command::BuiltIn(BuiltIn::Swap)]);
+        let (trace, result) = program.trace(vec![], &Interrupt::new(), Limits::default());
+        assert!(result.is_err());
+        assert_eq!(Err(Error::NotEnoughValues), trace[1].after);
+        assert_eq!(
+            "Command  Stack\n\
+             \x20        []\n\
+             1        [1]\n\
+             swap     error: NotEnoughValues\n",
+            table(&trace)
+        );
+    }
+}
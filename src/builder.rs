@@ -0,0 +1,147 @@
+use std::io::{self, BufReader, IsTerminal, Read, Write};
+use std::path::PathBuf;
+
+use crate::image::ImageFile;
+use crate::input::Input;
+use crate::{HistoryFile, InitFile, Interpreter, Interrupt, Language};
+
+/// Configures an `Interpreter` before it is built. By default it
+/// greets the user, prompts with the lowercased language name, reads
+/// from a line editor on the terminal, and writes to stdout and stderr.
+pub struct InterpreterBuilder<L> {
+    language: L,
+    banner: Option<String>,
+    prompt: Option<String>,
+    continuation_prompt: Option<String>,
+    history: HistoryFile,
+    image: ImageFile,
+    init: InitFile,
+    input: Option<Input>,
+    output: Option<Box<dyn Write>>,
+    errors: Option<Box<dyn Write>>,
+}
+
+/// A continuation prompt as wide as `prompt`, like `.......> ` for
+/// `postfix> `.
+pub(crate) fn continuation_prompt(prompt: &str) -> String {
+    let width = prompt.chars().count().saturating_sub(1);
+    format!("{:.>width$} ", ">", width = width)
+}
+
+impl<L> InterpreterBuilder<L>
+where
+    L: Language,
+{
+    pub fn new(language: L) -> InterpreterBuilder<L> {
+        InterpreterBuilder {
+            language,
+            banner: None,
+            prompt: None,
+            continuation_prompt: None,
+            history: HistoryFile::Default,
+            image: ImageFile::Default,
+            init: InitFile::Default,
+            input: None,
+            output: None,
+            errors: None,
+        }
+    }
+
+    /// The greeting printed when the REPL starts. An empty banner
+    /// prints nothing.
+    pub fn banner(mut self, banner: impl Into<String>) -> InterpreterBuilder<L> {
+        self.banner = Some(banner.into());
+        self
+    }
+
+    /// The prompt for a new form. By default it follows the name of
+    /// the language, like `postfix> `.
+    pub fn prompt(mut self, prompt: impl Into<String>) -> InterpreterBuilder<L> {
+        self.prompt = Some(prompt.into());
+        self
+    }
+
+    /// The prompt for the lines after the first of a form that spans
+    /// several lines. By default it's as wide as the prompt.
+    pub fn continuation_prompt(mut self, prompt: impl Into<String>) -> InterpreterBuilder<L> {
+        self.continuation_prompt = Some(prompt.into());
+        self
+    }
+
+    pub fn history(mut self, history: HistoryFile) -> InterpreterBuilder<L> {
+        self.history = history;
+        self
+    }
+
+    /// The session image to restore at startup, or `None` for none.
+    pub fn image(mut self, image: Option<PathBuf>) -> InterpreterBuilder<L> {
+        self.image = image.into();
+        self
+    }
+
+    /// The file to evaluate before the first prompt.
+    pub fn init(mut self, init: InitFile) -> InterpreterBuilder<L> {
+        self.init = init;
+        self
+    }
+
+    /// Read lines from `input` instead of the terminal. The prompts are
+    /// written to the output.
+    pub fn input(mut self, input: impl Read + 'static) -> InterpreterBuilder<L> {
+        self.input = Some(Input::reader(BufReader::new(input)));
+        self
+    }
+
+    /// Where prompts, values and the output of meta-commands go.
+    pub fn output(mut self, output: impl Write + 'static) -> InterpreterBuilder<L> {
+        self.output = Some(Box::new(output));
+        self
+    }
+
+    /// Where errors and warnings go.
+    pub fn errors(mut self, errors: impl Write + 'static) -> InterpreterBuilder<L> {
+        self.errors = Some(Box::new(errors));
+        self
+    }
+
+    pub fn build(self) -> Interpreter<L> {
+        let mut language = self.language;
+        let name = language.name().to_string();
+        let banner = self
+            .banner
+            .unwrap_or_else(|| format!("Welcome to the {} interpreter!", name));
+        let color_errors = self.errors.is_none() && io::stderr().is_terminal();
+
+        let interrupt = Interrupt::new();
+        language.set_interrupt(interrupt.clone());
+
+        Interpreter {
+            banner,
+            prompt: self.prompt,
+            continuation_prompt: self.continuation_prompt,
+            input: self.input.unwrap_or_else(Input::terminal),
+            output: self.output.unwrap_or_else(|| Box::new(io::stdout())),
+            errors: self.errors.unwrap_or_else(|| Box::new(io::stderr())),
+            color_errors,
+            language,
+            commands: vec![],
+            history: self.history,
+            image: self.image,
+            init: self.init,
+            recording: None,
+            interrupt,
+            had_error: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_continuation_prompt() {
+        assert_eq!(".......> ", continuation_prompt("postfix> "));
+        assert_eq!("> ", continuation_prompt("> "));
+    }
+}
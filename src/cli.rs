@@ -0,0 +1,224 @@
+//! The command line shared by the language binaries:
+//!
+//! ```text
+//! <lang> [--init <file>] [--record <file>] [--rpc | --listen <host:port> | --socket <path>]
+//! ```
+//!
+//! Without a mode flag the binary runs the REPL. The init file is
+//! evaluated in every mode, once for `--rpc` and at the start of every
+//! session for `--listen` and `--socket`.
+
+use std::io;
+use std::path::Path;
+use std::slice;
+
+use crate::{init, InitFile, Interpreter, Language, Server};
+
+/// What to do with the language.
+#[derive(Debug, PartialEq)]
+pub enum Mode {
+    Repl,
+    Rpc,
+    Listen(String),
+    #[cfg(unix)]
+    Socket(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub init: Option<String>,
+    pub record: Option<String>,
+    pub mode: Mode,
+}
+
+pub fn parse_args(args: &[String]) -> Result<Options, String> {
+    parse_args_with(args, |arg, _| Err(format!("unexpected argument `{}`", arg)))
+}
+
+/// Parse `args` like `parse_args`, handing any argument it doesn't know
+/// to `other` along with the arguments after it, so a binary can take
+/// options of its own.
+pub fn parse_args_with(
+    args: &[String],
+    mut other: impl FnMut(&str, &mut slice::Iter<String>) -> Result<(), String>,
+) -> Result<Options, String> {
+    let mut options = Options {
+        init: None,
+        record: None,
+        mode: Mode::Repl,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |what: &str| {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("`{}` needs {}", arg, what))
+        };
+        let mode = match arg.as_str() {
+            "--init" => {
+                options.init = Some(value("a file name")?);
+                continue;
+            }
+            "--record" => {
+                options.record = Some(value("a file name")?);
+                continue;
+            }
+            "--rpc" => Mode::Rpc,
+            "--listen" => Mode::Listen(value("an address")?),
+            #[cfg(unix)]
+            "--socket" => Mode::Socket(value("a file name")?),
+            _ => {
+                other(arg, &mut args)?;
+                continue;
+            }
+        };
+        if options.mode != Mode::Repl {
+            return Err("give only one of `--rpc`, `--listen` and `--socket`".to_string());
+        }
+        options.mode = mode;
+    }
+    if options.record.is_some() && options.mode != Mode::Repl {
+        return Err("`--record` only records the REPL".to_string());
+    }
+    Ok(options)
+}
+
+/// Run a language binary with the command line `args`, returning its
+/// exit code. `make_language` makes the language for the REPL and for
+/// each session of a server, and `configure` adds the language's
+/// meta-commands to the REPL.
+pub fn run<L>(
+    args: &[String],
+    make_language: impl Fn() -> L + Send + Sync + 'static,
+    configure: impl FnOnce(&mut Interpreter<L>),
+) -> i32
+where
+    L: Language + Send + 'static,
+{
+    match try_run(args, make_language, configure) {
+        Ok(code) => code,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            1
+        }
+    }
+}
+
+fn try_run<L>(
+    args: &[String],
+    make_language: impl Fn() -> L + Send + Sync + 'static,
+    configure: impl FnOnce(&mut Interpreter<L>),
+) -> Result<i32, String>
+where
+    L: Language + Send + 'static,
+{
+    let options = parse_args(args)?;
+    let mut interpreter = Interpreter::new(make_language());
+    configure(&mut interpreter);
+    if let Some(path) = &options.init {
+        interpreter.set_init(InitFile::Path(path.into()));
+    }
+    if let Some(path) = &options.record {
+        interpreter.record(path)?;
+    }
+    match &options.mode {
+        Mode::Repl => Ok(interpreter.run()),
+        Mode::Rpc => Ok(interpreter.run_rpc()),
+        Mode::Listen(address) => serve(server(make_language, options.init).listen_tcp(address)),
+        #[cfg(unix)]
+        Mode::Socket(path) => serve(server(make_language, options.init).listen_unix(path)),
+    }
+}
+
+/// A server whose sessions each start with a language that has
+/// evaluated the init file, if there is one.
+fn server<L>(
+    make_language: impl Fn() -> L + Send + Sync + 'static,
+    init: Option<String>,
+) -> Server<L>
+where
+    L: Language + Send + 'static,
+{
+    Server::per_session(move || {
+        let mut language = make_language();
+        if let Some(path) = &init {
+            for problem in init::run(&mut language, Path::new(path)) {
+                eprintln!("{}", problem);
+            }
+        }
+        language
+    })
+}
+
+fn serve(result: io::Result<()>) -> Result<i32, String> {
+    result.map(|()| 0).map_err(|error| error.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::fs;
+
+    use crate::test_support::{Adder, Buffer};
+
+    fn options(args: &[&str]) -> Result<Options, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        parse_args(&args)
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            Ok(Options {
+                init: None,
+                record: None,
+                mode: Mode::Repl,
+            }),
+            options(&[])
+        );
+        assert_eq!(
+            Ok(Options {
+                init: Some("defs.rc".to_string()),
+                record: None,
+                mode: Mode::Listen("localhost:4000".to_string()),
+            }),
+            options(&["--init", "defs.rc", "--listen", "localhost:4000"])
+        );
+        assert_eq!(
+            Some("session.transcript".to_string()),
+            options(&["--record", "session.transcript"]).unwrap().record
+        );
+        assert_eq!(
+            Err("`--record` only records the REPL".to_string()),
+            options(&["--rpc", "--record", "session.transcript"])
+        );
+        assert_eq!(Mode::Rpc, options(&["--rpc"]).unwrap().mode);
+        assert_eq!(
+            Err("`--init` needs a file name".to_string()),
+            options(&["--init"])
+        );
+        assert!(options(&["--listen"]).is_err());
+        assert!(options(&["--rpc", "--listen", "localhost:4000"]).is_err());
+        assert_eq!(
+            Err("unexpected argument `--verbose`".to_string()),
+            options(&["--verbose"])
+        );
+        assert!(options(&["prog.pf"]).is_err());
+    }
+
+    #[test]
+    fn test_server_sessions_run_init() {
+        let path = std::env::temp_dir().join(format!("dcpl-cli-init-{}", std::process::id()));
+        fs::write(&path, "10\n").unwrap();
+        let server = server(Adder::new, Some(path.display().to_string()));
+        let request = r#"{"jsonrpc": "2.0", "id": 1, "method": "eval", "params": {"code": "5"}}"#;
+        for _ in 0..2 {
+            let output = Buffer::default();
+            server.serve_connection(request.as_bytes(), output.clone());
+            let response = output.text();
+            assert!(response.contains(r#""values":["15"]"#), "{}", response);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
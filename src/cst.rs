@@ -0,0 +1,423 @@
+//! A lossless concrete syntax tree for source files.
+//!
+//! `SExpParser` throws away whitespace and comments, which is all a
+//! language needs. Tools that rewrite source, like the formatter here,
+//! need to keep them. A `SourceFile` records every character of its
+//! input as either a form or trivia, prints back exactly the text it
+//! was parsed from, and converts to the `SExp`s a language evaluates.
+
+use std::fmt;
+
+use pest::error::LineColLocation;
+use pest::iterators::Pair;
+use pest::Parser;
+use pest_derive::Parser;
+
+use crate::{parse_integer, parse_number, SExp, SyntaxError};
+
+#[derive(Parser)]
+#[grammar = "cst.pest"]
+struct CstParser;
+
+/// The widest a list may be to be printed on one line.
+const WIDTH: usize = 80;
+
+/// One piece of source text.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Element {
+    /// Spaces, tabs, commas and newlines.
+    Whitespace(String),
+    /// A comment, from `;;` to the end of the line.
+    Comment(String),
+    /// A number, string or symbol, along with its text.
+    Atom(String, SExp),
+    /// The elements between a pair of parentheses.
+    List(Vec<Element>),
+}
+
+impl Element {
+    fn from_pair(pair: Pair<Rule>) -> Result<Element, pest::error::Error<Rule>> {
+        let text = pair.as_str();
+        Ok(match pair.as_rule() {
+            Rule::whitespace => Element::Whitespace(text.into()),
+            Rule::comment => Element::Comment(text.into()),
+            Rule::list => Element::List(
+                pair.into_inner()
+                    .map(Element::from_pair)
+                    .collect::<Result<_, _>>()?,
+            ),
+            Rule::float => Element::Atom(text.into(), SExp::Float(parse_number(&pair)?)),
+            Rule::integer => Element::Atom(text.into(), parse_integer(&pair)?),
+            Rule::string => {
+                let content = &text[1..text.len() - 1]; // drop the quotes
+                Element::Atom(text.into(), SExp::string(content))
+            }
+            Rule::symbol => Element::Atom(text.into(), SExp::symbol(text)),
+            _ => unreachable!(),
+        })
+    }
+
+    pub fn is_trivia(&self) -> bool {
+        match self {
+            Element::Whitespace(_) | Element::Comment(_) => true,
+            Element::Atom(..) | Element::List(_) => false,
+        }
+    }
+
+    /// The form this element stands for, or `None` for trivia.
+    pub fn to_sexp(&self) -> Option<SExp> {
+        match self {
+            Element::Whitespace(_) | Element::Comment(_) => None,
+            Element::Atom(_, sexp) => Some(sexp.clone()),
+            Element::List(elements) => Some(SExp::List(
+                elements.iter().filter_map(Element::to_sexp).collect(),
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Element {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Element::Whitespace(text) | Element::Comment(text) | Element::Atom(text, _) => {
+                write!(f, "{}", text)
+            }
+            Element::List(elements) => {
+                write!(f, "(")?;
+                for element in elements {
+                    write!(f, "{}", element)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// Every element of a source file, in order. Displaying it reproduces
+/// the text it was parsed from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceFile {
+    pub elements: Vec<Element>,
+}
+
+impl SourceFile {
+    pub fn parse(text: impl AsRef<str>) -> Result<SourceFile, SyntaxError> {
+        let to_syntax_error = |error: pest::error::Error<Rule>| {
+            let (line, column) = match error.line_col {
+                LineColLocation::Pos(pos) | LineColLocation::Span(pos, _) => pos,
+            };
+            SyntaxError {
+                line,
+                column,
+                message: error.variant.message().into_owned(),
+            }
+        };
+        let mut pairs = CstParser::parse(Rule::file, text.as_ref()).map_err(to_syntax_error)?;
+        let elements = pairs
+            .next()
+            .unwrap()
+            .into_inner()
+            .filter(|pair| pair.as_rule() != Rule::EOI)
+            .map(Element::from_pair)
+            .collect::<Result<_, _>>()
+            .map_err(to_syntax_error)?;
+        Ok(SourceFile { elements })
+    }
+
+    /// The top-level forms, as `SExpParser::parse_file` would read them.
+    pub fn to_sexps(&self) -> Vec<SExp> {
+        self.elements.iter().filter_map(Element::to_sexp).collect()
+    }
+
+    /// The file laid out in the standard style. Comments are kept, and
+    /// formatting the result again changes nothing.
+    pub fn format(&self) -> String {
+        let mut out = String::new();
+        for (idx, item) in items(&self.elements).iter().enumerate() {
+            if idx > 0 {
+                out.push('\n');
+                if item.blank_before {
+                    out.push('\n');
+                }
+            }
+            item.write(&mut out);
+        }
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl fmt::Display for SourceFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        for element in &self.elements {
+            write!(f, "{}", element)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reformat the source `text`.
+pub fn format(text: impl AsRef<str>) -> Result<String, SyntaxError> {
+    SourceFile::parse(text).map(|file| file.format())
+}
+
+/// A form or a comment on a line of its own, as the formatter sees it.
+struct Item<'a> {
+    element: &'a Element,
+    /// A comment that followed the form on the same line.
+    trailing: Option<&'a str>,
+    /// Whether there was an empty line before the item.
+    blank_before: bool,
+}
+
+impl<'a> Item<'a> {
+    fn ends_line(&self) -> bool {
+        self.trailing.is_some() || matches!(self.element, Element::Comment(_))
+    }
+
+    fn write(&self, out: &mut String) {
+        match self.element {
+            Element::Comment(text) => out.push_str(text.trim_end()),
+            element => write_form(element, out),
+        }
+        if let Some(comment) = self.trailing {
+            out.push(' ');
+            out.push_str(comment.trim_end());
+        }
+    }
+}
+
+/// Group `elements` into items, attaching each comment that shares a
+/// line with the form before it to that form.
+fn items(elements: &[Element]) -> Vec<Item<'_>> {
+    let mut items: Vec<Item> = vec![];
+    let mut newlines = 0;
+    for element in elements {
+        match element {
+            Element::Whitespace(text) => newlines += text.matches('\n').count(),
+            Element::Comment(text) => {
+                let follows_form = items
+                    .last()
+                    .is_some_and(|item| !item.ends_line() && newlines == 0);
+                if follows_form {
+                    items.last_mut().unwrap().trailing = Some(text);
+                } else {
+                    items.push(Item {
+                        element,
+                        trailing: None,
+                        blank_before: newlines > 1,
+                    });
+                }
+                newlines = 0;
+            }
+            _ => {
+                items.push(Item {
+                    element,
+                    trailing: None,
+                    blank_before: newlines > 1,
+                });
+                newlines = 0;
+            }
+        }
+    }
+    items
+}
+
+fn column(out: &str) -> usize {
+    let line_start = out.rfind('\n').map_or(0, |idx| idx + 1);
+    out[line_start..].chars().count()
+}
+
+/// The form on one line, if it has no comments or line breaks.
+fn flat(element: &Element) -> Option<String> {
+    match element {
+        Element::Whitespace(_) => Some(String::new()),
+        Element::Comment(_) => None,
+        Element::Atom(text, _) if text.contains('\n') => None,
+        Element::Atom(text, _) => Some(text.clone()),
+        Element::List(elements) => {
+            let mut forms = vec![];
+            for element in elements.iter().filter(|element| !element.is_trivia()) {
+                forms.push(flat(element)?);
+            }
+            if elements
+                .iter()
+                .any(|element| matches!(element, Element::Comment(_)))
+            {
+                return None;
+            }
+            Some(format!("({})", forms.join(" ")))
+        }
+    }
+}
+
+/// Write a list on one line if it fits. Otherwise its head and the
+/// atoms after it go on the first line (or the head and one list, like
+/// the parameters of a `lambda`), and each remaining item gets a line
+/// of its own, indented two past the open paren.
+fn write_form(element: &Element, out: &mut String) {
+    let elements = match element {
+        Element::List(elements) => elements,
+        _ => {
+            out.push_str(&element.to_string());
+            return;
+        }
+    };
+
+    let start = column(out);
+    if let Some(text) = flat(element) {
+        if start + text.chars().count() <= WIDTH {
+            out.push_str(&text);
+            return;
+        }
+    }
+
+    let items = items(elements);
+    let first_line = first_line_len(&items);
+    out.push('(');
+    let indent = " ".repeat(start + 2);
+    for (idx, item) in items.iter().enumerate() {
+        if idx > 0 {
+            if idx < first_line {
+                out.push(' ');
+            } else {
+                out.push('\n');
+                out.push_str(&indent);
+            }
+        }
+        item.write(out);
+    }
+    if items.last().is_some_and(Item::ends_line) {
+        out.push('\n');
+        out.push_str(&" ".repeat(start));
+    }
+    out.push(')');
+}
+
+/// How many of `items` go on the first line of a broken list.
+fn first_line_len(items: &[Item]) -> usize {
+    let mut len = 0;
+    for item in items {
+        let is_atom = matches!(item.element, Element::Atom(..));
+        let fits = match len {
+            0 => true,
+            1 => is_atom || matches!(item.element, Element::List(_)),
+            _ => is_atom && matches!(items[len - 1].element, Element::Atom(..)),
+        };
+        if !fits {
+            break;
+        }
+        len += 1;
+        if item.ends_line() || (len == 2 && !is_atom) {
+            break;
+        }
+    }
+    len
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::SExpParser;
+
+    const SOURCE: &str = ";; Squares\n\
+                          (def sq 1 ;; one argument\n\
+                          \x20 (1 nget mul))\n\
+                          \n\
+                          \n\
+                          (sq 3)  ;; nine\n";
+
+    #[test]
+    fn test_lossless() {
+        let file = SourceFile::parse(SOURCE).unwrap();
+        assert_eq!(SOURCE, file.to_string());
+        assert_eq!(SExpParser::parse_file(SOURCE).unwrap(), file.to_sexps());
+    }
+
+    #[test]
+    fn test_trivia() {
+        let file = SourceFile::parse("(a ;; b\n, c)").unwrap();
+        assert_eq!(
+            vec![Element::List(vec![
+                Element::Atom("a".into(), SExp::symbol("a")),
+                Element::Whitespace(" ".into()),
+                Element::Comment(";; b".into()),
+                Element::Whitespace("\n, ".into()),
+                Element::Atom("c".into(), SExp::symbol("c")),
+            ])],
+            file.elements
+        );
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let error = SourceFile::parse("(a\n (b)").unwrap_err();
+        assert_eq!((2, 5), (error.line, error.column));
+    }
+
+    #[cfg(not(feature = "bignum"))]
+    #[test]
+    fn test_integer_too_large() {
+        let error =
+            SourceFile::parse("(a\n 1000000000000000000000000000000000000000000)").unwrap_err();
+        assert_eq!((2, 2), (error.line, error.column));
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(
+            ";; Squares\n\
+             (def sq 1 ;; one argument\n\
+             \x20 (1 nget mul))\n\
+             \n\
+             (sq 3) ;; nine\n",
+            format(SOURCE).unwrap()
+        );
+        assert_eq!(
+            "(a b (c d))\n(e)\n",
+            format("(a\n  b (c\n d)) (e)").unwrap()
+        );
+        assert_eq!("", format("").unwrap());
+    }
+
+    #[test]
+    fn test_format_long_list() {
+        let body = "(add x x x x x x x x x x x x x x x x x x x x x x x x x x x x x x x x x x x)";
+        let source = format!("(set! f (lambda (x) {}))", body);
+        assert_eq!(
+            format!("(set! f\n  (lambda (x)\n    {}))\n", body),
+            format(&source).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_format_comment_before_paren() {
+        assert_eq!(
+            "(def f 0 1 ;; one\n)\n",
+            format("(def f 0 1 ;; one\n)").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_format_is_idempotent() {
+        let sources = [
+            SOURCE,
+            "(def f 0 1 ;; one\n)",
+            "( ;; leading\n a b)",
+            ";; only a comment",
+            "(set! f (lambda (x) (add x x x x x x x x x x x x x x x x x x x x x x x x x x x x x x x)))",
+            "(a \"multi\nline\" b)",
+        ];
+        for source in &sources {
+            let once = format(source).unwrap();
+            assert_eq!(once, format(&once).unwrap(), "{:?}", source);
+            assert_eq!(
+                SourceFile::parse(source).unwrap().to_sexps(),
+                SourceFile::parse(&once).unwrap().to_sexps()
+            );
+        }
+    }
+}
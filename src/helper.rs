@@ -0,0 +1,97 @@
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+
+/// The rustyline helper used by `Interpreter`. It completes from a
+/// snapshot of the names the language knew about when the prompt was
+/// shown.
+pub struct ReplHelper {
+    names: Vec<String>,
+}
+
+impl ReplHelper {
+    pub fn new(names: Vec<String>) -> ReplHelper {
+        ReplHelper { names }
+    }
+}
+
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || c == '(' || c == ')' || c == ','
+}
+
+/// Find the start of the word that ends at `pos`.
+fn word_start(line: &str, pos: usize) -> usize {
+    line[..pos]
+        .char_indices()
+        .rev()
+        .find(|&(_, c)| is_delimiter(c))
+        .map(|(idx, c)| idx + c.len_utf8())
+        .unwrap_or(0)
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context,
+    ) -> Result<(usize, Vec<String>), ReadlineError> {
+        let start = word_start(line, pos);
+        let prefix = &line[start..pos];
+        let candidates = self
+            .names
+            .iter()
+            .filter(|name| name.starts_with(prefix))
+            .cloned()
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Helper for ReplHelper {}
+
+impl Highlighter for ReplHelper {}
+
+impl Hinter for ReplHelper {
+    fn hint(&self, _line: &str, _pos: usize, _ctx: &Context) -> Option<String> {
+        None
+    }
+}
+
+/// Every line is accepted as it is. The `Interpreter` asks for more
+/// lines with the continuation prompt until a form is finished.
+impl Validator for ReplHelper {
+    fn is_valid(&self, _line: &str) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_word_start_empty() {
+        assert_eq!(0, word_start("", 0));
+    }
+
+    #[test]
+    fn test_word_start_first_word() {
+        assert_eq!(0, word_start("ad", 2));
+    }
+
+    #[test]
+    fn test_word_start_in_list() {
+        assert_eq!(10, word_start("(def foo (ad", 12));
+    }
+
+    #[test]
+    fn test_word_start_after_space() {
+        assert_eq!(3, word_start("(a ", 3));
+    }
+}
@@ -0,0 +1,154 @@
+use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+/// The environment variable that overrides the directory history files
+/// are kept in. Setting it to an empty string disables history.
+pub const HISTORY_DIR_VAR: &str = "DCPL_HISTORY_DIR";
+
+/// Where an `Interpreter` keeps the history of its inputs between
+/// sessions.
+#[derive(Clone, Debug, PartialEq)]
+pub enum HistoryFile {
+    /// `$DCPL_HISTORY_DIR`, or `$XDG_DATA_HOME/dcpl` when that isn't
+    /// set, with a file name derived from the language name.
+    Default,
+    /// Exactly this file.
+    Path(PathBuf),
+    /// Don't load or save any history.
+    Disabled,
+}
+
+impl HistoryFile {
+    /// The file history for `language_name` should be kept in, if any.
+    pub fn resolve(&self, language_name: &str) -> Option<PathBuf> {
+        match self {
+            HistoryFile::Default => data_file(HISTORY_DIR_VAR, language_name, "history"),
+            HistoryFile::Path(path) => Some(path.clone()),
+            HistoryFile::Disabled => None,
+        }
+    }
+}
+
+/// The file where `language_name` keeps data of the given `kind`, e.g.
+/// `history`: in the directory named by the environment variable
+/// `dir_var` if it is set, or else in the data directory. `None` if
+/// there is no directory.
+pub(crate) fn data_file(dir_var: &str, language_name: &str, kind: &str) -> Option<PathBuf> {
+    let dir = default_dir(
+        env::var_os(dir_var),
+        env::var_os("XDG_DATA_HOME"),
+        env::var_os("HOME"),
+    )?;
+    Some(dir.join(file_name(language_name, kind)))
+}
+
+fn non_empty(value: Option<OsString>) -> Option<OsString> {
+    value.filter(|value| !value.is_empty())
+}
+
+fn default_dir(
+    history_dir: Option<OsString>,
+    data_home: Option<OsString>,
+    home: Option<OsString>,
+) -> Option<PathBuf> {
+    if let Some(dir) = history_dir {
+        return non_empty(Some(dir)).map(PathBuf::from);
+    }
+
+    let data_home = non_empty(data_home)
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| non_empty(home).map(|home| PathBuf::from(home).join(".local/share")))?;
+    Some(data_home.join("dcpl"))
+}
+
+/// Turn a language name like `L.I.S.P.` into a safe file name stem
+/// like `lisp`.
+pub(crate) fn file_stem(language_name: &str) -> String {
+    let name: String = language_name
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    if name.is_empty() {
+        "dcpl".to_string()
+    } else {
+        name
+    }
+}
+
+/// Turn a language name like `L.I.S.P.` and a kind of data like
+/// `history` into a safe file name like `lisp_history`.
+fn file_name(language_name: &str, kind: &str) -> String {
+    format!("{}_{}", file_stem(language_name), kind)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn some(value: &str) -> Option<OsString> {
+        Some(value.into())
+    }
+
+    #[test]
+    fn test_file_name_lisp() {
+        assert_eq!("lisp_history", file_name("L.I.S.P.", "history"));
+    }
+
+    #[test]
+    fn test_file_name_postfix() {
+        assert_eq!("postfix_history", file_name("Postfix", "history"));
+    }
+
+    #[test]
+    fn test_file_name_nothing_left() {
+        assert_eq!("dcpl_history", file_name("../..", "history"));
+    }
+
+    #[test]
+    fn test_default_dir_override() {
+        assert_eq!(
+            Some(PathBuf::from("/tmp/hist")),
+            default_dir(some("/tmp/hist"), some("/data"), some("/home/me"))
+        );
+    }
+
+    #[test]
+    fn test_default_dir_disabled() {
+        assert_eq!(None, default_dir(some(""), some("/data"), some("/home/me")));
+    }
+
+    #[test]
+    fn test_default_dir_xdg() {
+        assert_eq!(
+            Some(PathBuf::from("/data/dcpl")),
+            default_dir(None, some("/data"), some("/home/me"))
+        );
+    }
+
+    #[test]
+    fn test_default_dir_relative_xdg_ignored() {
+        assert_eq!(
+            Some(PathBuf::from("/home/me/.local/share/dcpl")),
+            default_dir(None, some("data"), some("/home/me"))
+        );
+    }
+
+    #[test]
+    fn test_default_dir_nowhere() {
+        assert_eq!(None, default_dir(None, None, None));
+    }
+
+    #[test]
+    fn test_resolve_path() {
+        let history = HistoryFile::Path("hist.txt".into());
+        assert_eq!(Some(PathBuf::from("hist.txt")), history.resolve("Postfix"));
+    }
+
+    #[test]
+    fn test_resolve_disabled() {
+        assert_eq!(None, HistoryFile::Disabled.resolve("Postfix"));
+    }
+}
@@ -0,0 +1,131 @@
+//! Session images: the state of a language saved as the forms that
+//! rebuild it, one per line, so the file can be read and edited by
+//! hand.
+//!
+//! ```text
+//! ;; Postfix session image
+//! (def sq 1 1 nget mul)
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{history, FormReader, SExp};
+
+/// The environment variable that overrides the directory the default
+/// image is kept in. Setting it to an empty string disables it.
+pub const IMAGE_DIR_VAR: &str = "DCPL_IMAGE_DIR";
+
+/// The image `:save` writes to when no file is named and that the
+/// interactive REPL restores at startup.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum ImageFile {
+    /// `<lang>_image` in `$DCPL_IMAGE_DIR`, or `$XDG_DATA_HOME/dcpl`
+    /// when that isn't set, named after the language being run at the
+    /// time.
+    Default,
+    Path(PathBuf),
+    Disabled,
+}
+
+impl ImageFile {
+    pub fn resolve(&self, language_name: &str) -> Option<PathBuf> {
+        match self {
+            ImageFile::Default => history::data_file(IMAGE_DIR_VAR, language_name, "image"),
+            ImageFile::Path(path) => Some(path.clone()),
+            ImageFile::Disabled => None,
+        }
+    }
+}
+
+impl From<Option<PathBuf>> for ImageFile {
+    fn from(path: Option<PathBuf>) -> ImageFile {
+        match path {
+            Some(path) => ImageFile::Path(path),
+            None => ImageFile::Disabled,
+        }
+    }
+}
+
+pub(crate) fn render(language_name: &str, forms: &[SExp]) -> String {
+    let mut text = format!(";; {} session image\n", language_name);
+    for form in forms {
+        text.push_str(&format!("{}\n", form));
+    }
+    text
+}
+
+pub(crate) fn save(path: &Path, language_name: &str, forms: &[SExp]) -> Result<(), String> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)
+            .map_err(|error| format!("could not write {}: {}", path.display(), error))?;
+    }
+    fs::write(path, render(language_name, forms))
+        .map_err(|error| format!("could not write {}: {}", path.display(), error))
+}
+
+pub(crate) fn load(path: &Path) -> Result<Vec<SExp>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|error| format!("could not read {}: {}", path.display(), error))?;
+    let mut reader = FormReader::new();
+    for line in text.lines() {
+        reader.push_line(line);
+    }
+
+    let mut forms = vec![];
+    while let Some(form) = reader.next_form().or_else(|| reader.finish()) {
+        let sexp = form
+            .parse()
+            .map_err(|error| format!("{}: {}", path.display(), error))?;
+        forms.push(sexp);
+    }
+    Ok(forms)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn forms() -> Vec<SExp> {
+        vec![
+            SExp::List(vec![
+                SExp::symbol("def"),
+                SExp::symbol("sq"),
+                SExp::Integer(1),
+                SExp::List(vec![SExp::Integer(1), SExp::symbol("nget")]),
+            ]),
+            SExp::string("hi"),
+        ]
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            ";; Postfix session image\n(def sq 1 (1 nget))\n\"hi\"\n",
+            render("Postfix", &forms())
+        );
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = std::env::temp_dir().join(format!("dcpl-image-{}", std::process::id()));
+        let path = dir.join("nested").join("postfix_image");
+        save(&path, "Postfix", &forms()).unwrap();
+        assert_eq!(Ok(forms()), load(&path));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_empty_image() {
+        let path = std::env::temp_dir().join(format!("dcpl-empty-{}", std::process::id()));
+        save(&path, "Postfix", &[]).unwrap();
+        assert_eq!(Ok(vec![]), load(&path));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_missing() {
+        let error = load(Path::new("/nonexistent/dcpl/image")).unwrap_err();
+        assert!(error.starts_with("could not read /nonexistent/dcpl/image"));
+    }
+}
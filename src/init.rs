@@ -0,0 +1,148 @@
+//! Init files: source evaluated when the interactive REPL starts, so
+//! users can preload their definitions.
+
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{history, reader, Form, Language};
+
+/// The file an `Interpreter` evaluates before its first prompt.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InitFile {
+    /// `<lang>.rc` in `$XDG_CONFIG_HOME/dcpl`, or `~/.config/dcpl` when
+    /// that isn't set, if the file exists.
+    Default,
+    /// Exactly this file, which must exist.
+    Path(PathBuf),
+    /// Don't evaluate any init file.
+    Disabled,
+}
+
+impl InitFile {
+    /// The init file for `language_name`, if any.
+    pub fn resolve(&self, language_name: &str) -> Option<PathBuf> {
+        match self {
+            InitFile::Default => {
+                let dir = config_dir(env::var_os("XDG_CONFIG_HOME"), env::var_os("HOME"))?;
+                Some(dir.join(format!("{}.rc", history::file_stem(language_name))))
+                    .filter(|path| path.exists())
+            }
+            InitFile::Path(path) => Some(path.clone()),
+            InitFile::Disabled => None,
+        }
+    }
+}
+
+fn config_dir(config_home: Option<OsString>, home: Option<OsString>) -> Option<PathBuf> {
+    let config_home = config_home
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| {
+            home.filter(|home| !home.is_empty())
+                .map(|home| PathBuf::from(home).join(".config"))
+        })?;
+    Some(config_home.join("dcpl"))
+}
+
+/// The forms in the file at `path`, with the line and column each one
+/// starts at.
+pub(crate) fn load(path: &Path) -> Result<Vec<Form>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|error| format!("could not read {}: {}", path.display(), error))?;
+    Ok(reader::read_forms(&text))
+}
+
+/// Something to report about evaluating an init file.
+pub(crate) enum Problem {
+    /// A form failed, or the file couldn't be read.
+    Error(String),
+    /// The language warned about a form.
+    Warning(String),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Problem::Error(error) => write!(f, "Error: {}", error),
+            Problem::Warning(warning) => write!(f, "Warning: {}", warning),
+        }
+    }
+}
+
+/// Evaluate the init file at `path` in `language`, ignoring the values.
+pub(crate) fn run<L: Language>(language: &mut L, path: &Path) -> Vec<Problem> {
+    let forms = match load(path) {
+        Ok(forms) => forms,
+        Err(error) => return vec![Problem::Error(error)],
+    };
+
+    let mut problems = vec![];
+    for form in forms {
+        let result = match form.parse() {
+            Ok(expr) => language
+                .eval(expr)
+                .map(|_| ())
+                .map_err(|error| format!("line {}: {:?}", form.line, error)),
+            Err(error) => Err(error.to_string()),
+        };
+        if let Err(error) = result {
+            problems.push(Problem::Error(format!("{}: {}", path.display(), error)));
+        }
+        for warning in language.take_warnings() {
+            let warning = format!("{}: line {}: {}", path.display(), form.line, warning);
+            problems.push(Problem::Warning(warning));
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn some(value: &str) -> Option<OsString> {
+        Some(value.into())
+    }
+
+    #[test]
+    fn test_config_dir_xdg() {
+        assert_eq!(
+            Some(PathBuf::from("/config/dcpl")),
+            config_dir(some("/config"), some("/home/me"))
+        );
+    }
+
+    #[test]
+    fn test_config_dir_home() {
+        assert_eq!(
+            Some(PathBuf::from("/home/me/.config/dcpl")),
+            config_dir(some("config"), some("/home/me"))
+        );
+        assert_eq!(None, config_dir(None, None));
+    }
+
+    #[test]
+    fn test_resolve_path() {
+        let init = InitFile::Path("defs.rc".into());
+        assert_eq!(Some(PathBuf::from("defs.rc")), init.resolve("Postfix"));
+        assert_eq!(None, InitFile::Disabled.resolve("Postfix"));
+    }
+
+    #[test]
+    fn test_load_keeps_lines() {
+        let path = std::env::temp_dir().join(format!("dcpl-init-{}", std::process::id()));
+        fs::write(
+            &path,
+            ";; helpers\n(def a 0 1)\n\n(def b\n 0 2) (def c 0 3)\n",
+        )
+        .unwrap();
+        let forms = load(&path).unwrap();
+        let lines: Vec<usize> = forms.iter().map(|form| form.line).collect();
+        assert_eq!(vec![2, 4, 5], lines);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,166 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+#[cfg(feature = "editor")]
+use rustyline::error::ReadlineError;
+#[cfg(feature = "editor")]
+use rustyline::Editor;
+
+#[cfg(feature = "editor")]
+use crate::helper::ReplHelper;
+use crate::Interrupt;
+
+/// What reading a line of input produced.
+pub(crate) enum Line {
+    Text(String),
+    /// Ctrl-C was pressed while the line was being read.
+    Interrupted,
+    Eof,
+}
+
+/// Where an `Interpreter` reads its input lines from.
+pub(crate) enum Input {
+    /// A line editor on the terminal, with completion and history.
+    #[cfg(feature = "editor")]
+    Editor(Editor<ReplHelper>),
+    /// Any other source of lines. The prompts are written to the
+    /// interpreter's output, and history is only kept in memory unless
+    /// it is saved.
+    Reader {
+        reader: Box<dyn BufRead>,
+        history: Vec<String>,
+        /// Whether the lines come from stdin, where a person may be
+        /// typing them.
+        terminal: bool,
+    },
+}
+
+impl Input {
+    /// Read from the terminal: with a line editor when the `editor`
+    /// feature is enabled, or plain lines from stdin when it isn't.
+    #[cfg(feature = "editor")]
+    pub fn terminal() -> Input {
+        Input::Editor(Editor::new())
+    }
+
+    /// Read from the terminal: with a line editor when the `editor`
+    /// feature is enabled, or plain lines from stdin when it isn't.
+    #[cfg(not(feature = "editor"))]
+    pub fn terminal() -> Input {
+        Input::Reader {
+            reader: Box::new(io::BufReader::new(io::stdin())),
+            history: vec![],
+            terminal: true,
+        }
+    }
+
+    pub fn reader(reader: impl BufRead + 'static) -> Input {
+        Input::Reader {
+            reader: Box::new(reader),
+            history: vec![],
+            terminal: false,
+        }
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        match self {
+            #[cfg(feature = "editor")]
+            Input::Editor(_) => true,
+            Input::Reader { terminal, .. } => *terminal,
+        }
+    }
+
+    /// Offer `names` as completions on the next line.
+    #[cfg(feature = "editor")]
+    pub fn set_names(&mut self, names: Vec<String>) {
+        if let Input::Editor(editor) = self {
+            editor.set_helper(Some(ReplHelper::new(names)));
+        }
+    }
+
+    /// Offer `names` as completions on the next line. Without a line
+    /// editor there is nothing to complete with.
+    #[cfg(not(feature = "editor"))]
+    pub fn set_names(&mut self, _names: Vec<String>) {}
+
+    /// Read a line after writing `prompt`. The line editor sees Ctrl-C
+    /// as a key, but a plain reader can't stop reading, so a line it
+    /// read while `interrupt` was triggered counts as interrupted.
+    pub fn read_line(
+        &mut self,
+        prompt: &str,
+        output: &mut dyn Write,
+        interrupt: &Interrupt,
+    ) -> io::Result<Line> {
+        match self {
+            #[cfg(feature = "editor")]
+            Input::Editor(editor) => match editor.readline(prompt) {
+                Ok(line) => Ok(Line::Text(line)),
+                Err(ReadlineError::Interrupted) => Ok(Line::Interrupted),
+                Err(ReadlineError::Eof) => Ok(Line::Eof),
+                Err(ReadlineError::Io(error)) => Err(error),
+                #[allow(unreachable_patterns)]
+                Err(error) => Err(io::Error::other(error.to_string())),
+            },
+            Input::Reader { reader, .. } => {
+                write!(output, "{}", prompt)?;
+                output.flush()?;
+                let mut line = String::new();
+                if reader.read_line(&mut line)? == 0 {
+                    return Ok(Line::Eof);
+                }
+                if interrupt.is_triggered() {
+                    return Ok(Line::Interrupted);
+                }
+                let len = line.trim_end_matches(&['\r', '\n'][..]).len();
+                line.truncate(len);
+                Ok(Line::Text(line))
+            }
+        }
+    }
+
+    pub fn add_history_entry(&mut self, entry: &str) {
+        match self {
+            #[cfg(feature = "editor")]
+            Input::Editor(editor) => {
+                editor.add_history_entry(entry);
+            }
+            Input::Reader { history, .. } => history.push(entry.to_string()),
+        }
+    }
+
+    pub fn history(&self) -> Vec<String> {
+        match self {
+            #[cfg(feature = "editor")]
+            Input::Editor(editor) => editor.get_history_const().iter().cloned().collect(),
+            Input::Reader { history, .. } => history.clone(),
+        }
+    }
+
+    pub fn load_history(&mut self, path: &Path) {
+        match self {
+            #[cfg(feature = "editor")]
+            Input::Editor(editor) => {
+                editor.load_history(path).ok();
+            }
+            Input::Reader { history, .. } => {
+                if let Ok(text) = fs::read_to_string(path) {
+                    *history = text.lines().map(String::from).collect();
+                }
+            }
+        }
+    }
+
+    pub fn save_history(&mut self, path: &Path) -> Result<(), String> {
+        match self {
+            #[cfg(feature = "editor")]
+            Input::Editor(editor) => editor.save_history(path).map_err(|error| error.to_string()),
+            Input::Reader { history, .. } => {
+                let mut text = history.join("\n");
+                text.push('\n');
+                fs::write(path, text).map_err(|error| error.to_string())
+            }
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A request to stop the evaluation in progress. Languages check it
+/// between steps and give up with an error of their own when it has
+/// been triggered. Clones share the same flag.
+#[derive(Clone, Debug, Default)]
+pub struct Interrupt(Arc<AtomicBool>);
+
+impl Interrupt {
+    pub fn new() -> Interrupt {
+        Interrupt::default()
+    }
+
+    pub fn trigger(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Forget any earlier trigger, before starting a new evaluation.
+    pub fn clear(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+
+    pub(crate) fn flag(&self) -> Arc<AtomicBool> {
+        self.0.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_clones_share_the_flag() {
+        let interrupt = Interrupt::new();
+        let clone = interrupt.clone();
+        assert!(!clone.is_triggered());
+        interrupt.trigger();
+        assert!(clone.is_triggered());
+        clone.clear();
+        assert!(!interrupt.is_triggered());
+    }
+}
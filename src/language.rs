@@ -0,0 +1,56 @@
+use std::fmt;
+
+use crate::{Interrupt, SExp};
+
+/// A language that can be driven by an `Interpreter`.
+pub trait Language {
+    /// The result of evaluating a top-level form.
+    type Value: fmt::Display;
+
+    /// The ways evaluating a top-level form can fail.
+    type Error: fmt::Debug;
+
+    /// The human readable name of the language, e.g. `"Postfix"`.
+    fn name(&self) -> &str;
+
+    /// Evaluate one top-level form. Forms that only change the state
+    /// of the language, like definitions, produce `Ok(None)`.
+    fn eval(&mut self, expr: SExp) -> Result<Option<Self::Value>, Self::Error>;
+
+    /// Throw away every definition and return to the initial state.
+    fn reset(&mut self);
+
+    /// Hand the language the `Interrupt` it should check while
+    /// evaluating, so long-running forms can be stopped. Languages that
+    /// can't be interrupted ignore it.
+    fn set_interrupt(&mut self, _interrupt: Interrupt) {}
+
+    /// Forms that rebuild the current definitions when they are
+    /// evaluated in order after a `reset`. Used to save sessions.
+    fn save(&self) -> Vec<SExp> {
+        vec![]
+    }
+
+    /// All of the names known to the language that start with `prefix`.
+    fn complete(&self, _prefix: &str) -> Vec<String> {
+        vec![]
+    }
+
+    /// A short description of what `name` means in the language.
+    fn describe(&self, _name: &str) -> Option<String> {
+        None
+    }
+
+    /// The name `form` gives a definition to, if it is a definition.
+    /// It is only looked at, never evaluated.
+    fn defines(&self, _form: &SExp) -> Option<String> {
+        None
+    }
+
+    /// Warnings about the last form evaluated that don't make it fail,
+    /// like a definition that can never work. They are taken, so the
+    /// next call gives none until another form has been evaluated.
+    fn take_warnings(&mut self) -> Vec<String> {
+        vec![]
+    }
+}
@@ -1,15 +1,68 @@
 use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
+#[cfg(feature = "bignum")]
+pub use num_bigint::BigInt;
+
+use pest::error::ErrorVariant;
 use pest::iterators::{Pair, Pairs};
-use pest::Parser;
+use pest::{Parser, RuleType};
 use pest_derive::Parser;
 
-use rustyline::completion::Completer;
-use rustyline::error::ReadlineError;
-use rustyline::highlight::Highlighter;
-use rustyline::hint::Hinter;
-use rustyline::validate::Validator;
-use rustyline::{Context, Editor, Helper};
+use signal_hook::consts::SIGINT;
+
+mod builder;
+pub use crate::builder::InterpreterBuilder;
+
+pub mod cli;
+
+pub mod cst;
+
+#[cfg(feature = "editor")]
+mod helper;
+
+mod history;
+pub use crate::history::{HistoryFile, HISTORY_DIR_VAR};
+
+mod image;
+use crate::image::ImageFile;
+pub use crate::image::IMAGE_DIR_VAR;
+
+mod init;
+pub use crate::init::InitFile;
+
+mod input;
+use crate::input::{Input, Line};
+
+mod interrupt;
+pub use crate::interrupt::Interrupt;
+
+mod language;
+pub use crate::language::Language;
+
+mod meta;
+pub use crate::meta::MetaCommand;
+
+mod reader;
+pub use crate::reader::{Form, FormReader, SyntaxError};
+
+pub mod lsp;
+
+pub mod registry;
+
+pub mod rpc;
+
+mod server;
+pub use crate::server::Server;
+
+pub mod transcript;
+use crate::transcript::Recording;
+
+#[cfg(test)]
+mod test_support;
 
 #[derive(Parser)]
 #[grammar = "sexp.pest"]
@@ -18,28 +71,26 @@ pub struct SExpParser;
 type ParseError = pest::error::Error<Rule>;
 
 impl SExpParser {
-    pub fn parse_file(input: impl AsRef<str>) -> Vec<SExp> {
+    pub fn parse_file(input: impl AsRef<str>) -> Result<Vec<SExp>, ParseError> {
         let input = input.as_ref();
-        let file = SExpParser::parse(Rule::file, input)
-            .expect("unsuccessful parse...")
-            .next()
-            .unwrap();
+        let file = SExpParser::parse(Rule::file, input)?.next().unwrap();
+        let sexps = file.into_inner().filter(|pair| pair.as_rule() != Rule::EOI);
 
-        SExpParser::parse_list(file.into_inner())
+        sexps.map(SExpParser::parse_rule).collect()
     }
 
     pub fn parse_line(input: impl AsRef<str>) -> Result<SExp, ParseError> {
         let input = input.as_ref();
         let sexp = SExpParser::parse(Rule::sexp, input)?.next().unwrap();
 
-        Ok(SExpParser::parse_rule(sexp))
+        SExpParser::parse_rule(sexp)
     }
 
-    fn parse_rule(pair: Pair<Rule>) -> SExp {
-        match pair.as_rule() {
-            Rule::list => SExp::List(SExpParser::parse_list(pair.into_inner())),
-            Rule::float => SExp::Float(pair.as_str().parse().unwrap()),
-            Rule::integer => SExp::Integer(pair.as_str().parse().unwrap()),
+    fn parse_rule(pair: Pair<Rule>) -> Result<SExp, ParseError> {
+        Ok(match pair.as_rule() {
+            Rule::list => SExp::List(SExpParser::parse_list(pair.into_inner())?),
+            Rule::float => SExp::Float(parse_number(&pair)?),
+            Rule::integer => parse_integer(&pair)?,
             Rule::string => {
                 let content = pair.as_str();
                 let len = content.len();
@@ -48,41 +99,43 @@ impl SExpParser {
             }
             Rule::symbol => SExp::Symbol(pair.as_str().into()),
             _ => unreachable!(),
-        }
+        })
     }
 
-    fn parse_list(pairs: Pairs<Rule>) -> Vec<SExp> {
+    fn parse_list(pairs: Pairs<Rule>) -> Result<Vec<SExp>, ParseError> {
         pairs.map(SExpParser::parse_rule).collect()
     }
 }
 
-impl Completer for SExpParser {
-    type Candidate = String;
-
-    fn complete(
-        &self,
-        _line: &str,
-        _pos: usize,
-        _ctx: &Context,
-    ) -> Result<(usize, Vec<String>), ReadlineError> {
-        Ok((0, Vec::with_capacity(0)))
+/// Read the integer literal `pair` matched. With `bignum`, literals
+/// that don't fit in an `i128` become `SExp::BigInteger`s.
+pub(crate) fn parse_integer<R: RuleType>(pair: &Pair<R>) -> Result<SExp, pest::error::Error<R>> {
+    #[cfg(feature = "bignum")]
+    {
+        parse_number(pair).map(SExp::big_integer)
     }
-}
-
-impl Helper for SExpParser {}
-
-impl Highlighter for SExpParser {}
-
-impl Hinter for SExpParser {
-    fn hint(&self, _line: &str, _pos: usize, _ctx: &Context) -> Option<String> {
-        None
+    #[cfg(not(feature = "bignum"))]
+    {
+        parse_number(pair).map(SExp::Integer)
     }
 }
 
-impl Validator for SExpParser {
-    fn is_valid(&self, line: &str) -> bool {
-        SExpParser::parse_line(line).is_ok()
-    }
+/// Read the number `pair` matched, reporting a literal that doesn't fit
+/// in `T` as a syntax error instead of panicking.
+pub(crate) fn parse_number<R, T>(pair: &Pair<R>) -> Result<T, pest::error::Error<R>>
+where
+    R: RuleType,
+    T: std::str::FromStr,
+    T::Err: fmt::Display,
+{
+    pair.as_str().parse().map_err(|error| {
+        pest::error::Error::new_from_span(
+            ErrorVariant::CustomError {
+                message: format!("invalid number {}: {}", pair.as_str(), error),
+            },
+            pair.as_span(),
+        )
+    })
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -90,11 +143,25 @@ pub enum SExp {
     List(Vec<SExp>),
     Float(f64),
     Integer(i128),
+    /// An integer outside the range of `Integer`.
+    #[cfg(feature = "bignum")]
+    BigInteger(BigInt),
     String(String),
     Symbol(String),
 }
 
 impl SExp {
+    /// `value` as an `Integer` if it fits, otherwise a `BigInteger`.
+    #[cfg(feature = "bignum")]
+    pub fn big_integer(value: BigInt) -> SExp {
+        use std::convert::TryFrom;
+
+        match i128::try_from(&value) {
+            Ok(value) => SExp::Integer(value),
+            Err(_) => SExp::BigInteger(value),
+        }
+    }
+
     pub fn string(content: impl Into<String>) -> SExp {
         SExp::String(content.into())
     }
@@ -139,53 +206,36 @@ impl SExp {
     }
 
     pub fn is_list(&self) -> bool {
-        match self {
-            SExp::List(_) => true,
-            _ => false,
-        }
+        matches!(self, SExp::List(_))
     }
 
     pub fn is_atom(&self) -> bool {
-        match self {
-            SExp::List(_) => false,
-            _ => true,
-        }
+        !self.is_list()
     }
 
     pub fn is_symbol(&self) -> bool {
-        match self {
-            SExp::Symbol(_) => true,
-            _ => false,
-        }
+        matches!(self, SExp::Symbol(_))
     }
 
     pub fn is_string(&self) -> bool {
-        match self {
-            SExp::String(_) => true,
-            _ => false,
-        }
+        matches!(self, SExp::String(_))
     }
 
     pub fn is_number(&self) -> bool {
-        use self::SExp::*;
-        match self {
-            Integer(_) | Float(_) => true,
-            _ => false,
-        }
+        self.is_integer() || self.is_float()
     }
 
     pub fn is_integer(&self) -> bool {
         match self {
             SExp::Integer(_) => true,
+            #[cfg(feature = "bignum")]
+            SExp::BigInteger(_) => true,
             _ => false,
         }
     }
 
     pub fn is_float(&self) -> bool {
-        match self {
-            SExp::Float(_) => true,
-            _ => false,
-        }
+        matches!(self, SExp::Float(_))
     }
 }
 
@@ -204,75 +254,573 @@ impl fmt::Display for SExp {
             }
             Float(val) => write!(f, "{}", val),
             Integer(val) => write!(f, "{}", val),
+            #[cfg(feature = "bignum")]
+            BigInteger(val) => write!(f, "{}", val),
             Symbol(content) => write!(f, "{}", content),
             String(content) => write!(f, "\"{}\"", content),
         }
     }
 }
 
-pub struct Interpreter<F> {
-    name: String,
-    prompt: String,
-    editor: Editor<SExpParser>,
-    interpret: F,
+pub struct Interpreter<L> {
+    banner: String,
+    prompt: Option<String>,
+    continuation_prompt: Option<String>,
+    input: Input,
+    output: Box<dyn Write>,
+    errors: Box<dyn Write>,
+    color_errors: bool,
+    language: L,
+    commands: Vec<MetaCommand<L>>,
+    history: HistoryFile,
+    image: ImageFile,
+    init: InitFile,
+    recording: Option<Recording>,
+    interrupt: Interrupt,
+    had_error: bool,
+}
+
+/// Why a form read by the REPL produced no value.
+enum Failure {
+    Invalid(SyntaxError),
+    /// The language's error, formatted with `{:?}`.
+    Eval(String),
 }
 
-impl<F> Interpreter<F>
+enum Flow {
+    Continue,
+    Quit,
+}
+
+impl<L> Interpreter<L>
 where
-    F: FnMut(SExp) -> Option<String>,
+    L: Language,
 {
-    pub fn new(name: &'static str, interpret: F) -> Interpreter<F> {
-        Interpreter::new_with_prompts(name, format!("{}> ", name.to_lowercase()), interpret)
+    pub fn new(language: L) -> Interpreter<L> {
+        Interpreter::builder(language).build()
     }
 
-    pub fn new_with_prompts(
-        name: impl Into<String>,
-        prompt: impl Into<String>,
-        interpret: F,
-    ) -> Interpreter<F> {
-        Interpreter {
-            name: name.into(),
-            prompt: prompt.into(),
-            editor: Editor::new(),
-            interpret,
-        }
+    pub fn new_with_prompts(language: L, prompt: impl Into<String>) -> Interpreter<L> {
+        Interpreter::builder(language).prompt(prompt).build()
     }
 
-    pub fn run(&mut self) {
-        println!("Welcome to the {} interpreter!", self.name);
-        self.editor.set_helper(Some(SExpParser));
-        self.editor.load_history(&self.history_file_name()).ok();
+    /// Start configuring an interpreter for `language`. Anything that
+    /// isn't configured gets the same default as `new`.
+    pub fn builder(language: L) -> InterpreterBuilder<L> {
+        InterpreterBuilder::new(language)
+    }
+
+    /// Choose where the input history is loaded from and saved to.
+    pub fn set_history(&mut self, history: HistoryFile) {
+        self.history = history;
+    }
+
+    /// Choose the session image that `:save` writes by default and
+    /// the interactive REPL restores at startup, or `None` to start
+    /// fresh every time.
+    pub fn set_image(&mut self, image: Option<PathBuf>) {
+        self.image = image.into();
+    }
+
+    /// Choose the init file the interactive REPL evaluates at startup.
+    /// `run_script` and `run_rpc` evaluate it too if it's given by path.
+    pub fn set_init(&mut self, init: InitFile) {
+        self.init = init;
+    }
+
+    /// Write every form evaluated from now on, and what it produced, to
+    /// a transcript at `path`. See the `transcript` module for the
+    /// format.
+    pub fn record(&mut self, path: impl AsRef<Path>) -> Result<(), String> {
+        self.recording = Some(Recording::create(path.as_ref(), self.language.name())?);
+        Ok(())
+    }
+
+    /// Register a language-specific meta-command, run as `:name args`.
+    pub fn add_command(&mut self, command: MetaCommand<L>) {
+        self.commands.push(command);
+    }
+
+    /// Run the REPL until the user quits. A form may span several
+    /// lines, which are read with the continuation prompt. Ctrl-C
+    /// interrupts the form being evaluated, or abandons the form being
+    /// entered: at once with the line editor, and without it when the
+    /// line being typed ends, since reading stdin can't be stopped. The
+    /// init file is evaluated before the first prompt. When reading from
+    /// a standard input that isn't a terminal the forms are read with
+    /// `run_script` instead. The returned exit code is non-zero if any
+    /// input failed to parse or evaluate.
+    pub fn run(&mut self) -> i32 {
+        if self.input.is_terminal() && !io::stdin().is_terminal() {
+            return self.run_script(io::stdin().lock());
+        }
+
+        if !self.banner.is_empty() {
+            let banner = self.banner.clone();
+            self.print(banner);
+        }
+        // the editor reads Ctrl-C as a key at the prompt, so the signal
+        // only arrives while a form is being evaluated; without it, a
+        // Ctrl-C at the prompt triggers the interrupt and the plain
+        // reader discards the line
+        let sigint = if self.input.is_terminal() {
+            signal_hook::flag::register(SIGINT, self.interrupt.flag()).ok()
+        } else {
+            None
+        };
+        let history_path = self.history.resolve(self.language.name());
+        if let Some(path) = &history_path {
+            self.input.load_history(path);
+        }
+        let image = self.image.resolve(self.language.name());
+        if let Some(path) = image.filter(|path| path.exists()) {
+            self.restore_image(&path);
+        }
+        if let Some(path) = self.init.resolve(self.language.name()) {
+            self.run_init(&path);
+        }
 
+        let mut reader = FormReader::new();
+        let mut entry = String::new();
         loop {
-            let line = self.editor.readline(&self.prompt);
-
-            match line {
-                Ok(line) => match SExpParser::parse_line(&line) {
-                    Ok(sexp) => {
-                        if let Some(output) = (self.interpret)(sexp) {
-                            println!("{}", output);
-                        }
-                        self.editor.add_history_entry(line.as_ref());
-                    }
-                    Err(error) => println!("Invalid input: {:?}", error),
-                },
-                Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => {
+            let idle = reader.is_idle();
+            let (prompt, continuation_prompt) = self.prompts();
+            let prompt = if idle {
+                self.input.set_names(self.language.complete(""));
+                prompt
+            } else {
+                continuation_prompt
+            };
+
+            self.interrupt.clear();
+            let line = match self
+                .input
+                .read_line(&prompt, &mut *self.output, &self.interrupt)
+            {
+                Ok(Line::Text(line)) => line,
+                Ok(Line::Interrupted) => {
+                    reader = FormReader::new();
+                    entry.clear();
+                    continue;
+                }
+                Ok(Line::Eof) => break,
+                Err(error) => {
+                    self.report_error(format!("Error: {}", error));
                     break;
                 }
-                Err(err) => {
-                    println!("Error: {:?}", err);
+            };
+
+            if idle {
+                if let Some((name, args)) = meta::parse(&line) {
+                    self.add_history_entry(&line);
+                    match self.meta(name, args) {
+                        Flow::Continue => continue,
+                        Flow::Quit => break,
+                    }
+                }
+            }
+
+            reader.push_line(&line);
+            if !entry.is_empty() {
+                entry.push('\n');
+            }
+            entry.push_str(&line);
+            while let Some(form) = reader.next_form() {
+                self.eval_entered_form(&form);
+            }
+
+            if reader.is_idle() {
+                if !entry.trim().is_empty() {
+                    self.add_history_entry(&entry);
+                }
+                reader = FormReader::new();
+                entry.clear();
+            }
+        }
+
+        if let Some(form) = reader.finish() {
+            self.eval_entered_form(&form);
+        }
+        if let Some(path) = &history_path {
+            self.save_history(path);
+        }
+        if let Some(sigint) = sigint {
+            signal_hook::low_level::unregister(sigint);
+        }
+
+        self.exit_code()
+    }
+
+    /// Evaluate every form in `input` with `run_piped`, after the init
+    /// file if one was given by path.
+    pub fn run_script(&mut self, input: impl BufRead) -> i32 {
+        self.run_named_init();
+        self.run_piped(input)
+    }
+
+    /// Evaluate every form in `input` without prompting. Each value is
+    /// written to the output on a line of its own and each failure to
+    /// the error output as a single line starting with `error: `. Lines
+    /// starting with `:` between forms are run as meta-commands.
+    pub fn run_piped(&mut self, input: impl BufRead) -> i32 {
+        let mut reader = FormReader::new();
+        for line in input.lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(error) => {
+                    self.report_error(format!("error: reading input: {}", error));
                     break;
                 }
+            };
+
+            if reader.is_idle() {
+                if let Some((name, args)) = meta::parse(&line) {
+                    if let Flow::Quit = self.meta(name, args) {
+                        return self.exit_code();
+                    }
+                    reader.push_line("");
+                    continue;
+                }
+            }
+
+            reader.push_line(&line);
+            while let Some(form) = reader.next_form() {
+                self.eval_form(&form);
             }
         }
 
-        self.editor
-            .save_history(&self.history_file_name())
-            .expect("saving history file failed...");
+        if let Some(form) = reader.finish() {
+            self.report_error(format!("error: line {}: unfinished form", form.line));
+        }
+        self.exit_code()
     }
 
-    fn history_file_name(&self) -> String {
-        format!("{}.txt", self.name)
+    /// Answer JSON-RPC requests on stdin until the client shuts the
+    /// server down, after evaluating the init file if one was given by
+    /// path. See the `rpc` module for the protocol.
+    pub fn run_rpc(&mut self) -> i32 {
+        self.run_named_init();
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        match rpc::serve(&mut self.language, stdin.lock(), stdout.lock()) {
+            Ok(()) => 0,
+            Err(error) => {
+                self.report_error(format!("error: {}", error));
+                1
+            }
+        }
+    }
+
+    fn exit_code(&self) -> i32 {
+        if self.had_error {
+            1
+        } else {
+            0
+        }
+    }
+
+    /// The prompts for a new form and for the rest of one that spans
+    /// several lines. Unless they were configured they follow the name
+    /// of the language, which may change during a session.
+    fn prompts(&self) -> (String, String) {
+        let prompt = self
+            .prompt
+            .clone()
+            .unwrap_or_else(|| format!("{}> ", self.language.name().to_lowercase()));
+        let continuation_prompt = self
+            .continuation_prompt
+            .clone()
+            .unwrap_or_else(|| builder::continuation_prompt(&prompt));
+        (prompt, continuation_prompt)
+    }
+
+    fn print(&mut self, text: impl fmt::Display) {
+        // there's nowhere left to report a failure to write the output
+        let _ = writeln!(self.output, "{}", text);
+    }
+
+    /// Evaluate a form read by `run_piped`.
+    fn eval_form(&mut self, form: &Form) {
+        match self.eval_recorded(form) {
+            Ok(Some(value)) => self.print(value),
+            Ok(None) => (),
+            Err(Failure::Invalid(error)) => self.report_error(format!("error: {}", error)),
+            Err(Failure::Eval(error)) => self.report_error(format!("error: {}", error)),
+        }
+    }
+
+    /// Evaluate a form entered at the prompt, returning whether it
+    /// parsed.
+    fn eval_entered_form(&mut self, form: &Form) -> bool {
+        match self.eval_recorded(form) {
+            Ok(Some(value)) => self.print(value),
+            Ok(None) => (),
+            Err(Failure::Invalid(error)) => {
+                self.report_error(format!("Invalid input: {}", error));
+                return false;
+            }
+            Err(Failure::Eval(error)) => self.report_error(format!("Error: {}", error)),
+        }
+        true
+    }
+
+    /// Parse and evaluate `form`, adding it and its result to the
+    /// transcript being recorded, if any.
+    fn eval_recorded(&mut self, form: &Form) -> Result<Option<String>, Failure> {
+        let result = match form.parse() {
+            Ok(sexp) => match self.language.eval(sexp) {
+                Ok(value) => Ok(value.map(|value| value.to_string())),
+                Err(error) => Err(Failure::Eval(format!("{:?}", error))),
+            },
+            Err(error) => Err(Failure::Invalid(error)),
+        };
+        self.report_warnings();
+
+        if let Some(recording) = &mut self.recording {
+            let output = match &result {
+                Ok(Some(value)) => transcript::value_lines(value),
+                Ok(None) => vec![],
+                Err(Failure::Invalid(error)) => {
+                    transcript::error_lines(&format!("invalid input: {}", error.message))
+                }
+                Err(Failure::Eval(error)) => transcript::error_lines(error),
+            };
+            if let Err(error) = recording.record(&form.text, &output) {
+                self.recording = None;
+                self.warn(format!("Stopped recording: {}", error));
+            }
+        }
+        result
+    }
+
+    fn add_history_entry(&mut self, line: &str) {
+        self.input.add_history_entry(line);
+    }
+
+    fn report_error(&mut self, message: String) {
+        self.had_error = true;
+        if self.color_errors {
+            self.warn(format!("\x1b[31m{}\x1b[0m", message));
+        } else {
+            self.warn(message);
+        }
+    }
+
+    /// Write the language's warnings about the last form to the error
+    /// output.
+    fn report_warnings(&mut self) {
+        for warning in self.language.take_warnings() {
+            self.warn(format!("Warning: {}", warning));
+        }
+    }
+
+    /// Write `message` to the error output without counting it as a
+    /// failure.
+    fn warn(&mut self, message: String) {
+        let _ = writeln!(self.errors, "{}", message);
+    }
+
+    fn meta(&mut self, name: &str, args: &str) -> Flow {
+        match name {
+            "help" => self.print_help(),
+            "quit" => return Flow::Quit,
+            "load" => self.load(args),
+            "reset" => self.reset(),
+            "time" => self.time(args),
+            "describe" => self.describe(args),
+            "history" => self.print_history(),
+            "save" => self.save_image(args),
+            "restore" => self.restore(args),
+            "record" => self.toggle_recording(args),
+            _ => match self.commands.iter_mut().find(|c| c.name() == name) {
+                Some(command) => {
+                    if let Some(output) = command.run(&mut self.language, args) {
+                        self.print(output);
+                    }
+                }
+                None => self.print(format!("Unknown command `:{}`, try :help", name)),
+            },
+        }
+        Flow::Continue
+    }
+
+    fn print_help(&mut self) {
+        let mut help = String::from("Meta-commands:");
+        for (usage, text) in meta::BUILTIN_HELP {
+            help.push_str(&format!("\n  {:<20}{}", usage, text));
+        }
+        for command in &self.commands {
+            help.push_str(&format!("\n  :{:<19}{}", command.name(), command.help()));
+        }
+        self.print(help);
+    }
+
+    fn load(&mut self, file_name: &str) {
+        if file_name.is_empty() {
+            self.print("Usage: :load <file>");
+            return;
+        }
+
+        let contents = match fs::read_to_string(file_name) {
+            Ok(contents) => contents,
+            Err(error) => {
+                self.report_error(format!("Error: could not read {}: {}", file_name, error));
+                return;
+            }
+        };
+
+        for form in reader::read_forms(&contents) {
+            self.eval_entered_form(&form);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.language.reset();
+        let message = format!("{} state reset.", self.language.name());
+        self.print(message);
+    }
+
+    fn time(&mut self, line: &str) {
+        let forms = reader::read_forms(line);
+        if forms.is_empty() {
+            self.print("Usage: :time <expr>");
+            return;
+        }
+
+        let start = Instant::now();
+        let mut parsed = true;
+        for form in forms {
+            parsed &= self.eval_entered_form(&form);
+        }
+        if parsed {
+            self.print(format!("Elapsed: {:?}", start.elapsed()));
+        }
+    }
+
+    fn describe(&mut self, name: &str) {
+        match self.language.describe(name) {
+            Some(description) => self.print(description),
+            None => self.print(format!("Nothing is known about `{}`", name)),
+        }
+    }
+
+    /// The file named by a `:save` or `:restore` argument, falling back
+    /// to the default image.
+    fn image_path(&self, file_name: &str) -> Option<PathBuf> {
+        if file_name.is_empty() {
+            self.image.resolve(self.language.name())
+        } else {
+            Some(PathBuf::from(file_name))
+        }
+    }
+
+    fn save_image(&mut self, file_name: &str) {
+        let path = match self.image_path(file_name) {
+            Some(path) => path,
+            None => {
+                self.print("Usage: :save <file>");
+                return;
+            }
+        };
+
+        let forms = self.language.save();
+        match image::save(&path, self.language.name(), &forms) {
+            Ok(()) => self.print(format!(
+                "Saved {} definitions to {}",
+                forms.len(),
+                path.display()
+            )),
+            Err(error) => self.report_error(format!("Error: {}", error)),
+        }
+    }
+
+    fn restore(&mut self, file_name: &str) {
+        match self.image_path(file_name) {
+            Some(path) => self.restore_image(&path),
+            None => self.print("Usage: :restore <file>"),
+        }
+    }
+
+    /// Replace the language's state with the image at `path`. Forms
+    /// that fail are reported and skipped.
+    fn restore_image(&mut self, path: &Path) {
+        let forms = match image::load(path) {
+            Ok(forms) => forms,
+            Err(error) => {
+                self.report_error(format!("Error: {}", error));
+                return;
+            }
+        };
+
+        self.language.reset();
+        for form in forms {
+            let source = form.to_string();
+            if let Err(error) = self.language.eval(form) {
+                self.report_error(format!("Error: restoring {}: {:?}", source, error));
+            }
+            self.report_warnings();
+        }
+        self.print(format!("Restored {}", path.display()));
+    }
+
+    /// Start recording to `file_name`, or stop recording if it's empty.
+    fn toggle_recording(&mut self, file_name: &str) {
+        if !file_name.is_empty() {
+            match self.record(file_name) {
+                Ok(()) => self.print(format!("Recording to {}", file_name)),
+                Err(error) => self.report_error(format!("Error: {}", error)),
+            }
+            return;
+        }
+        match self.recording.take() {
+            Some(recording) => self.print(format!(
+                "Stopped recording to {}",
+                recording.path().display()
+            )),
+            None => self.print("Usage: :record <file>"),
+        }
+    }
+
+    /// Evaluate the init file if it was asked for by name. Only the
+    /// interactive REPL looks for the default one.
+    fn run_named_init(&mut self) {
+        if let InitFile::Path(path) = self.init.clone() {
+            self.run_init(&path);
+        }
+    }
+
+    /// Evaluate the init file at `path`, quietly. Forms that fail are
+    /// reported with the file and line they're on, and the rest of the
+    /// file is still evaluated.
+    fn run_init(&mut self, path: &Path) {
+        for problem in init::run(&mut self.language, path) {
+            match problem {
+                init::Problem::Error(_) => self.report_error(problem.to_string()),
+                init::Problem::Warning(_) => self.warn(problem.to_string()),
+            }
+        }
+    }
+
+    fn print_history(&mut self) {
+        let history = self.input.history();
+        for (idx, entry) in history.iter().enumerate() {
+            self.print(format!("{:>5}  {}", idx + 1, entry));
+        }
+    }
+
+    fn save_history(&mut self, path: &Path) {
+        let saved = match path.parent() {
+            Some(dir) => fs::create_dir_all(dir).map_err(|error| error.to_string()),
+            None => Ok(()),
+        }
+        .and_then(|()| self.input.save_history(path));
+        if let Err(error) = saved {
+            self.warn(format!(
+                "Warning: could not save history to {}: {}",
+                path.display(),
+                error
+            ));
+        }
     }
 }
 
@@ -281,6 +829,8 @@ mod test {
     use super::SExp::*;
     use super::*;
 
+    use crate::test_support::{Adder, Buffer};
+
     fn parse(input: impl AsRef<str>) -> SExp {
         SExpParser::parse_line(input).expect("unexpected parse error")
     }
@@ -305,6 +855,21 @@ mod test {
         assert_eq!(Integer(-5), parse("-5"));
     }
 
+    #[cfg(not(feature = "bignum"))]
+    #[test]
+    fn test_parse_integer_too_large() {
+        assert!(SExpParser::parse_line("(1 1000000000000000000000000000000000000000000)").is_err());
+    }
+
+    #[cfg(feature = "bignum")]
+    #[test]
+    fn test_parse_big_integer() {
+        let text = "-1000000000000000000000000000000000000000000";
+        assert_eq!(BigInteger(text.parse().unwrap()), parse(text));
+        assert_eq!(text, parse(text).to_string());
+        assert_eq!(Integer(i128::MIN), parse(i128::MIN.to_string()));
+    }
+
     #[test]
     fn test_parse_float() {
         assert_eq!(Float(1.0), parse("1.0"));
@@ -330,6 +895,18 @@ mod test {
         assert_eq!(List(vec![Integer(1)]), parse("(1)"));
     }
 
+    #[test]
+    fn test_parse_file() {
+        let sexps = SExpParser::parse_file(";; two forms\n1\n(a b)\n").unwrap();
+        assert_eq!(
+            vec![
+                SExp::Integer(1),
+                SExp::List(vec![SExp::symbol("a"), SExp::symbol("b")])
+            ],
+            sexps
+        );
+    }
+
     #[test]
     fn test_parse_list() {
         assert_eq!(
@@ -337,4 +914,290 @@ mod test {
             parse("(1 2 3)")
         );
     }
+
+    fn builder(input: &'static str, output: &Buffer, errors: &Buffer) -> InterpreterBuilder<Adder> {
+        Interpreter::builder(Adder::default())
+            .banner("Hi!")
+            .history(HistoryFile::Disabled)
+            .image(None)
+            .init(InitFile::Disabled)
+            .input(input.as_bytes())
+            .output(output.clone())
+            .errors(errors.clone())
+    }
+
+    fn run_repl(input: &'static str) -> (i32, std::string::String, std::string::String) {
+        let (output, errors) = (Buffer::default(), Buffer::default());
+        let code = builder(input, &output, &errors).build().run();
+        (code, output.text(), errors.text())
+    }
+
+    #[test]
+    fn test_repl_values() {
+        let (code, output, errors) = run_repl("1\n2 3\n");
+        assert_eq!(0, code);
+        assert_eq!("Hi!\nadder> 1\nadder> 3\n6\nadder> ", output);
+        assert_eq!("", errors);
+    }
+
+    #[test]
+    fn test_repl_continuation_prompt() {
+        let (_, output, _) = run_repl("(1\n2\n3)\n");
+        assert_eq!("Hi!\nadder> .....> .....> 6\nadder> ", output);
+    }
+
+    #[test]
+    fn test_repl_custom_prompts() {
+        let (output, errors) = (Buffer::default(), Buffer::default());
+        builder("(1\n2)\n", &output, &errors)
+            .banner("")
+            .prompt("? ")
+            .continuation_prompt("| ")
+            .build()
+            .run();
+        assert_eq!("? | 3\n? ", output.text());
+    }
+
+    #[test]
+    fn test_repl_errors() {
+        let (code, output, errors) = run_repl("foo\n)\n4\n");
+        assert_eq!(1, code);
+        assert!(output.ends_with("adder> 4\nadder> "));
+        let errors: Vec<&str> = errors.lines().collect();
+        assert_eq!("Error: \"NotANumber\"", errors[0]);
+        assert!(errors[1].starts_with("Invalid input: line 1, column 1: "));
+    }
+
+    #[test]
+    fn test_repl_warnings() {
+        let (code, output, errors) = run_repl("()\n4\n");
+        assert_eq!(0, code);
+        assert_eq!("Hi!\nadder> 0\nadder> 4\nadder> ", output);
+        assert_eq!("Warning: () adds nothing\n", errors);
+    }
+
+    #[test]
+    fn test_repl_unfinished_form_at_end() {
+        let (code, _, errors) = run_repl("(1 2\n");
+        assert_eq!(1, code);
+        assert!(errors.starts_with("Invalid input: line 2, column 1: "));
+    }
+
+    #[test]
+    fn test_repl_meta_commands() {
+        let (_, output, _) = run_repl(":describe total\n5\n:describe total\n:quit\n6\n");
+        assert_eq!(
+            "Hi!\nadder> total = 0\nadder> 5\nadder> total = 5\nadder> ",
+            output
+        );
+    }
+
+    /// Gives one line per read, like a person typing at a terminal, and
+    /// has Ctrl-C pressed while the second line is being typed.
+    struct CtrlC {
+        lines: Vec<&'static str>,
+        reads: usize,
+        interrupt: Interrupt,
+    }
+
+    impl io::Read for CtrlC {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads += 1;
+            if self.reads == 2 {
+                self.interrupt.trigger();
+            }
+            if self.lines.is_empty() {
+                return Ok(0);
+            }
+            let line = self.lines.remove(0).as_bytes();
+            buf[..line.len()].copy_from_slice(line);
+            Ok(line.len())
+        }
+    }
+
+    #[test]
+    fn test_repl_interrupt_while_reading() {
+        let (output, errors) = (Buffer::default(), Buffer::default());
+        let mut interpreter = builder("", &output, &errors).build();
+        interpreter.input = Input::reader(io::BufReader::new(CtrlC {
+            lines: vec!["(1\n", "2\n", "5\n"],
+            reads: 0,
+            interrupt: interpreter.interrupt.clone(),
+        }));
+        assert_eq!(0, interpreter.run());
+        // the unfinished `(1` is abandoned along with the line
+        assert_eq!("Hi!\nadder> .....> adder> 5\nadder> ", output.text());
+        assert_eq!("", errors.text());
+    }
+
+    #[test]
+    fn test_repl_history() {
+        let (_, output, _) = run_repl("1\n(2\n3)\n:history\n");
+        assert!(output.ends_with("    1  1\n    2  (2\n3)\n    3  :history\nadder> "));
+    }
+
+    #[test]
+    fn test_repl_history_file() {
+        let path = std::env::temp_dir().join(format!("dcpl-repl-history-{}", std::process::id()));
+        let (output, errors) = (Buffer::default(), Buffer::default());
+        builder("1\n2\n", &output, &errors)
+            .history(HistoryFile::Path(path.clone()))
+            .build()
+            .run();
+        assert_eq!("1\n2\n", fs::read_to_string(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_repl_load() {
+        let path = std::env::temp_dir().join(format!("dcpl-repl-load-{}", std::process::id()));
+        fs::write(&path, "1\n;; a comment\n(2 3)\n").unwrap();
+        let input = format!(":load {}\n", path.display());
+        let (output, errors) = (Buffer::default(), Buffer::default());
+        Interpreter::builder(Adder::default())
+            .banner("")
+            .history(HistoryFile::Disabled)
+            .image(None)
+            .init(InitFile::Disabled)
+            .input(std::io::Cursor::new(input))
+            .output(output.clone())
+            .errors(errors.clone())
+            .build()
+            .run();
+        fs::remove_file(&path).unwrap();
+        assert_eq!("adder> 1\n6\nadder> ", output.text());
+        assert_eq!("", errors.text());
+    }
+
+    #[test]
+    fn test_repl_init_file() {
+        let path = std::env::temp_dir().join(format!("dcpl-repl-init-{}", std::process::id()));
+        fs::write(
+            &path,
+            "10
+foo
+
+(1 2
+",
+        )
+        .unwrap();
+        let (output, errors) = (Buffer::default(), Buffer::default());
+        let code = builder("5\n", &output, &errors)
+            .init(InitFile::Path(path.clone()))
+            .build()
+            .run();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(1, code);
+        assert_eq!("Hi!\nadder> 15\nadder> ", output.text());
+        let errors = errors.text();
+        let errors: Vec<&str> = errors.lines().collect();
+        assert_eq!(
+            format!("Error: {}: line 2: \"NotANumber\"", path.display()),
+            errors[0]
+        );
+        assert!(errors[1].starts_with(&format!("Error: {}: line 5, column 1: ", path.display())));
+    }
+
+    #[test]
+    fn test_run_script_init_file() {
+        let path = std::env::temp_dir().join(format!("dcpl-script-init-{}", std::process::id()));
+        fs::write(&path, "10\n").unwrap();
+        let (output, errors) = (Buffer::default(), Buffer::default());
+        let code = builder("", &output, &errors)
+            .init(InitFile::Path(path.clone()))
+            .build()
+            .run_script("5\n".as_bytes());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(0, code);
+        assert_eq!("15\n", output.text());
+    }
+
+    #[test]
+    fn test_repl_missing_init_file() {
+        let (output, errors) = (Buffer::default(), Buffer::default());
+        builder("1\n", &output, &errors)
+            .init(InitFile::Path("/nonexistent/dcpl.rc".into()))
+            .build()
+            .run();
+        assert!(errors
+            .text()
+            .starts_with("Error: could not read /nonexistent/dcpl.rc"));
+        assert_eq!("Hi!\nadder> 1\nadder> ", output.text());
+    }
+
+    #[test]
+    fn test_repl_record() {
+        let path = std::env::temp_dir().join(format!("dcpl-repl-record-{}", std::process::id()));
+        let input = format!(":record {}\n1\n(2\n3) foo\n)\n:record\n4\n", path.display());
+        let (output, errors) = (Buffer::default(), Buffer::default());
+        builder("", &output, &errors)
+            .input(io::Cursor::new(input.into_bytes()))
+            .build()
+            .run();
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(output
+            .text()
+            .contains(&format!("Stopped recording to {}", path.display())));
+        let recorded: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            vec![
+                ";; Adder session transcript",
+                "1",
+                ";;=> 1",
+                "(2",
+                "3)",
+                ";;=> 6",
+                "foo",
+                ";;!! \"NotANumber\"",
+                ")",
+            ],
+            recorded[..9].to_vec()
+        );
+        assert!(recorded[9].starts_with(";;!! invalid input: "));
+        assert_eq!(10, recorded.len());
+
+        let transcript = transcript::Transcript::parse(&text);
+        let replayed = transcript.replay(&mut Adder::default());
+        assert_eq!(None, transcript.diff(&replayed));
+    }
+
+    #[test]
+    fn test_repl_record_load_and_time() {
+        let id = std::process::id();
+        let source = std::env::temp_dir().join(format!("dcpl-record-load-{}", id));
+        let path = std::env::temp_dir().join(format!("dcpl-record-load-{}.transcript", id));
+        fs::write(&source, "1\n;; two more\n(2 foo)\n3\n").unwrap();
+        let input = format!(
+            ":record {}\n:load {}\n:time 4\n",
+            path.display(),
+            source.display()
+        );
+        let (output, errors) = (Buffer::default(), Buffer::default());
+        builder("", &output, &errors)
+            .input(io::Cursor::new(input.into_bytes()))
+            .build()
+            .run();
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&source).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            vec![
+                ";; Adder session transcript",
+                "1",
+                ";;=> 1",
+                "(2 foo)",
+                ";;!! \"NotANumber\"",
+                "3",
+                ";;=> 4",
+                "4",
+                ";;=> 8",
+            ],
+            text.lines().collect::<Vec<_>>()
+        );
+        assert!(output.text().contains("Elapsed: "));
+    }
 }
@@ -0,0 +1,481 @@
+//! A Language Server Protocol server for editing source files.
+//!
+//! Messages are JSON-RPC framed with `Content-Length` headers, as the
+//! protocol requires. Each open document is parsed with `SExpParser`
+//! and checked against the language registered for its extension. The
+//! server offers:
+//!
+//! - parse errors as diagnostics, published whenever a document is
+//!   opened or changed,
+//! - hover text from `Language::describe`, or the source of a
+//!   definition in the document,
+//! - go-to-definition for names defined in the document, as decided by
+//!   `Language::defines`,
+//! - document symbols for those definitions.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use pest::error::InputLocation;
+use pest::iterators::Pair;
+use pest::Parser;
+use serde_json::{json, Value};
+
+use crate::registry::{AnyLanguage, Registry};
+use crate::rpc::{Request, RpcError, INVALID_PARAMS, INVALID_REQUEST, METHOD_NOT_FOUND};
+use crate::{Language, Rule, SExp, SExpParser};
+
+const SYMBOL_KIND_VARIABLE: i64 = 13;
+const DIAGNOSTIC_SEVERITY_ERROR: i64 = 1;
+
+/// Read one message, or `None` at the end of the input.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header")
+    })?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+pub fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+/// A parsed form along with the bytes of the document it came from.
+#[derive(Debug)]
+struct Node {
+    start: usize,
+    end: usize,
+    sexp: SExp,
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn parse_file(text: &str) -> Result<Vec<Node>, pest::error::Error<Rule>> {
+        let file = SExpParser::parse(Rule::file, text)?.next().unwrap();
+        file.into_inner()
+            .filter(|pair| pair.as_rule() != Rule::EOI)
+            .map(Node::from_pair)
+            .collect()
+    }
+
+    fn from_pair(pair: Pair<Rule>) -> Result<Node, pest::error::Error<Rule>> {
+        let span = pair.as_span();
+        let children = match pair.as_rule() {
+            Rule::list => pair
+                .clone()
+                .into_inner()
+                .map(Node::from_pair)
+                .collect::<Result<_, _>>()?,
+            _ => vec![],
+        };
+        Ok(Node {
+            start: span.start(),
+            end: span.end(),
+            sexp: SExpParser::parse_rule(pair)?,
+            children,
+        })
+    }
+
+    fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset <= self.end
+    }
+
+    /// The innermost node at `offset`.
+    fn find(&self, offset: usize) -> Option<&Node> {
+        if !self.contains(offset) {
+            return None;
+        }
+        self.children
+            .iter()
+            .find_map(|child| child.find(offset))
+            .or(Some(self))
+    }
+}
+
+/// A name defined in a document: the whole defining form, and the
+/// symbol naming it.
+struct Definition<'a> {
+    name: String,
+    form: &'a Node,
+    name_node: &'a Node,
+}
+
+struct Document {
+    text: String,
+    nodes: Vec<Node>,
+    language: Option<AnyLanguage>,
+}
+
+impl Document {
+    /// Replace the text, returning the parse error if there is one. The
+    /// forms from the last text that parsed are kept until then.
+    fn update(&mut self, text: String) -> Option<pest::error::Error<Rule>> {
+        self.text = text;
+        match Node::parse_file(&self.text) {
+            Ok(nodes) => {
+                self.nodes = nodes;
+                None
+            }
+            Err(error) => Some(error),
+        }
+    }
+
+    fn symbol_at(&self, offset: usize) -> Option<&str> {
+        let node = self.nodes.iter().find_map(|node| node.find(offset))?;
+        match &node.sexp {
+            // The forms may be from an earlier text that parsed.
+            SExp::Symbol(name) if self.text.get(node.start..node.end) == Some(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn definitions(&self) -> Vec<Definition<'_>> {
+        let language = match &self.language {
+            Some(language) => language,
+            None => return vec![],
+        };
+        let mut definitions = vec![];
+        let mut pending: Vec<&Node> = self.nodes.iter().rev().collect();
+        while let Some(node) = pending.pop() {
+            if let Some(name) = language.defines(&node.sexp) {
+                let name_node = node
+                    .children
+                    .iter()
+                    .find(|child| child.sexp == SExp::Symbol(name.clone()))
+                    .unwrap_or(node);
+                definitions.push(Definition {
+                    name,
+                    form: node,
+                    name_node,
+                });
+            }
+            pending.extend(node.children.iter().rev());
+        }
+        definitions
+    }
+
+    fn definition(&self, name: &str) -> Option<Definition<'_>> {
+        self.definitions()
+            .into_iter()
+            .find(|definition| definition.name == name)
+    }
+
+    fn range(&self, start: usize, end: usize) -> Value {
+        json!({
+            "start": position(&self.text, start),
+            "end": position(&self.text, end),
+        })
+    }
+}
+
+/// The LSP position of the byte `offset` in `text`. Characters are
+/// counted in UTF-16 code units.
+fn position(text: &str, offset: usize) -> Value {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+    let character: usize = before[line_start..].chars().map(char::len_utf16).sum();
+    json!({ "line": line, "character": character })
+}
+
+/// The byte offset of an LSP position in `text`.
+fn offset(text: &str, position: &Value) -> Option<usize> {
+    let line = position.get("line")?.as_u64()? as usize;
+    let character = position.get("character")?.as_u64()? as usize;
+
+    let mut line_start = 0;
+    for _ in 0..line {
+        line_start += text[line_start..].find('\n')? + 1;
+    }
+    let mut units = 0;
+    for (idx, c) in text[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return Some(line_start + idx);
+        }
+        units += c.len_utf16();
+    }
+    Some(text.len())
+}
+
+pub struct LspServer {
+    registry: Registry,
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+}
+
+impl LspServer {
+    /// Documents are checked with the language in `registry` that their
+    /// extension belongs to.
+    pub fn new(registry: Registry) -> LspServer {
+        LspServer {
+            registry,
+            documents: HashMap::new(),
+            shutdown: false,
+        }
+    }
+
+    /// Answer messages read from `input` until `exit` is sent or the
+    /// input ends.
+    pub fn serve(&mut self, mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        while let Some(message) = read_message(&mut input)? {
+            let request = match Request::parse(&message) {
+                Ok(request) => request,
+                Err(response) => {
+                    write_message(&mut output, &response)?;
+                    continue;
+                }
+            };
+            if request.method == "exit" {
+                break;
+            }
+            for message in self.handle(&request) {
+                write_message(&mut output, &message)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The messages to send in reply to `request`: its response, and
+    /// any notifications it caused.
+    fn handle(&mut self, request: &Request) -> Vec<Value> {
+        let params = &request.params;
+        let mut messages = vec![];
+        let result = match request.method.as_str() {
+            _ if self.shutdown => Err(RpcError::new(INVALID_REQUEST, "the server has shut down")),
+            "initialize" => Ok(capabilities()),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/didOpen" => {
+                let document = &params["textDocument"];
+                let uri = document["uri"].as_str().unwrap_or_default();
+                let text = document["text"].as_str().unwrap_or_default();
+                messages.extend(self.open(uri, text));
+                Ok(Value::Null)
+            }
+            "textDocument/didChange" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                let changes = params["contentChanges"].as_array();
+                let text = changes
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str());
+                if let Some(text) = text {
+                    messages.extend(self.change(uri, text.to_string()));
+                }
+                Ok(Value::Null)
+            }
+            "textDocument/didClose" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                self.documents.remove(uri);
+                messages.push(diagnostics(uri, vec![]));
+                Ok(Value::Null)
+            }
+            "textDocument/hover" => self.hover(params),
+            "textDocument/definition" => self.definition(params),
+            "textDocument/documentSymbol" => self.document_symbols(params),
+            _ if request.id.is_none() => Ok(Value::Null),
+            method => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("unknown method `{}`", method),
+            )),
+        };
+        if let Some(response) = request.respond(result) {
+            messages.insert(0, response);
+        }
+        messages
+    }
+
+    fn open(&mut self, uri: &str, text: &str) -> Option<Value> {
+        let language = self.language_for(uri);
+        let document = Document {
+            text: String::new(),
+            nodes: vec![],
+            language,
+        };
+        self.documents.insert(uri.to_string(), document);
+        self.change(uri, text.to_string())
+    }
+
+    fn change(&mut self, uri: &str, text: String) -> Option<Value> {
+        let document = self.documents.get_mut(uri)?;
+        let error = match document.update(text) {
+            Some(error) => error,
+            None => return Some(diagnostics(uri, vec![])),
+        };
+        let (start, end) = match error.location {
+            InputLocation::Pos(pos) => (pos, pos),
+            InputLocation::Span(span) => span,
+        };
+        let diagnostic = json!({
+            "range": document.range(start, end),
+            "severity": DIAGNOSTIC_SEVERITY_ERROR,
+            "source": "dcpl",
+            "message": error.variant.message().into_owned(),
+        });
+        Some(diagnostics(uri, vec![diagnostic]))
+    }
+
+    fn language_for(&self, uri: &str) -> Option<AnyLanguage> {
+        let name = self.registry.for_path(uri)?;
+        self.registry
+            .registrations()
+            .iter()
+            .find(|registration| registration.name() == name)
+            .map(|registration| registration.make_language())
+    }
+
+    /// The open document and byte offset a position request is about.
+    fn locate(&self, params: &Value) -> Result<Option<(&Document, usize)>, RpcError> {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, "missing textDocument.uri"))?;
+        let document = match self.documents.get(uri) {
+            Some(document) => document,
+            None => return Ok(None),
+        };
+        Ok(offset(&document.text, &params["position"]).map(|offset| (document, offset)))
+    }
+
+    fn hover(&self, params: &Value) -> Result<Value, RpcError> {
+        let (document, offset) = match self.locate(params)? {
+            Some(located) => located,
+            None => return Ok(Value::Null),
+        };
+        let name = match document.symbol_at(offset) {
+            Some(name) => name,
+            None => return Ok(Value::Null),
+        };
+        let text = document
+            .definition(name)
+            .map(|definition| document.text[definition.form.start..definition.form.end].to_string())
+            .or_else(|| document.language.as_ref()?.describe(name));
+        Ok(match text {
+            Some(text) => json!({ "contents": { "kind": "plaintext", "value": text } }),
+            None => Value::Null,
+        })
+    }
+
+    fn definition(&self, params: &Value) -> Result<Value, RpcError> {
+        let (document, offset) = match self.locate(params)? {
+            Some(located) => located,
+            None => return Ok(Value::Null),
+        };
+        let definition = document
+            .symbol_at(offset)
+            .and_then(|name| document.definition(name));
+        Ok(match definition {
+            Some(definition) => json!({
+                "uri": params["textDocument"]["uri"],
+                "range": document.range(definition.name_node.start, definition.name_node.end),
+            }),
+            None => Value::Null,
+        })
+    }
+
+    fn document_symbols(&self, params: &Value) -> Result<Value, RpcError> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let document = match self.documents.get(uri) {
+            Some(document) => document,
+            None => return Ok(json!([])),
+        };
+        let symbols: Vec<Value> = document
+            .definitions()
+            .into_iter()
+            .map(|definition| {
+                json!({
+                    "name": definition.name,
+                    "kind": SYMBOL_KIND_VARIABLE,
+                    "range": document.range(definition.form.start, definition.form.end),
+                    "selectionRange": document.range(
+                        definition.name_node.start,
+                        definition.name_node.end
+                    ),
+                })
+            })
+            .collect();
+        Ok(json!(symbols))
+    }
+}
+
+fn capabilities() -> Value {
+    json!({
+        "capabilities": {
+            "textDocumentSync": 1,
+            "hoverProvider": true,
+            "definitionProvider": true,
+            "documentSymbolProvider": true,
+        },
+        "serverInfo": { "name": "dcpl-lsp" },
+    })
+}
+
+fn diagnostics(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_position_and_offset() {
+        let text = "(a\n  bé c)\n";
+        assert_eq!(json!({ "line": 0, "character": 1 }), position(text, 1));
+        assert_eq!(json!({ "line": 1, "character": 4 }), position(text, 8));
+        assert_eq!(Some(8), offset(text, &json!({ "line": 1, "character": 4 })));
+        assert_eq!(
+            Some(11),
+            offset(text, &json!({ "line": 1, "character": 99 }))
+        );
+        assert_eq!(None, offset(text, &json!({ "line": 5, "character": 0 })));
+    }
+
+    #[test]
+    fn test_find_node() {
+        let nodes = Node::parse_file("(def sq 1 (dup mul))").unwrap();
+        let node = nodes[0].find(12).unwrap();
+        assert_eq!(SExp::symbol("dup"), node.sexp);
+        assert_eq!((11, 14), (node.start, node.end));
+        assert_eq!(None, nodes[0].find(30).map(|node| node.start));
+    }
+
+    #[test]
+    fn test_read_and_write_message() {
+        let mut buffer = vec![];
+        write_message(&mut buffer, &json!({ "id": 1 })).unwrap();
+        assert_eq!(b"Content-Length: 8\r\n\r\n{\"id\":1}".to_vec(), buffer);
+
+        let mut input = &buffer[..];
+        assert_eq!(
+            Some("{\"id\":1}".to_string()),
+            read_message(&mut input).unwrap()
+        );
+        assert_eq!(None, read_message(&mut input).unwrap());
+    }
+}
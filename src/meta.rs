@@ -0,0 +1,109 @@
+type Handler<L> = dyn FnMut(&mut L, &str) -> Option<String>;
+
+/// A colon-prefixed command that is handled by the `Interpreter`
+/// itself instead of being passed on to the language.
+pub struct MetaCommand<L> {
+    name: String,
+    help: String,
+    run: Box<Handler<L>>,
+}
+
+impl<L> MetaCommand<L> {
+    pub fn new(
+        name: impl Into<String>,
+        help: impl Into<String>,
+        run: impl FnMut(&mut L, &str) -> Option<String> + 'static,
+    ) -> MetaCommand<L> {
+        MetaCommand {
+            name: name.into(),
+            help: help.into(),
+            run: Box::new(run),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn help(&self) -> &str {
+        &self.help
+    }
+
+    pub fn run(&mut self, language: &mut L, args: &str) -> Option<String> {
+        (self.run)(language, args)
+    }
+}
+
+pub(crate) const BUILTIN_HELP: &[(&str, &str)] = &[
+    (":help", "show this message"),
+    (":quit", "leave the interpreter"),
+    (":load <file>", "evaluate every form in <file>"),
+    (":reset", "discard all definitions"),
+    (
+        ":time <expr>",
+        "evaluate <expr> and report how long it took",
+    ),
+    (":describe <name>", "explain what <name> means"),
+    (":history", "show previous inputs"),
+    (":save [file]", "save the definitions to a session image"),
+    (
+        ":restore [file]",
+        "replace the definitions with a session image",
+    ),
+    (
+        ":record [file]",
+        "record forms and their output to <file>, or stop recording",
+    ),
+];
+
+/// Split a `:name args` line into the command name and the rest of
+/// the line, or return `None` if `line` isn't a meta-command.
+pub fn parse(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim();
+    if !line.starts_with(':') {
+        return None;
+    }
+
+    let line = &line[1..];
+    match line.find(char::is_whitespace) {
+        Some(idx) => Some((&line[..idx], line[idx..].trim())),
+        None => Some((line, "")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_not_meta() {
+        assert_eq!(None, parse("(add 1 2)"));
+    }
+
+    #[test]
+    fn test_parse_no_args() {
+        assert_eq!(Some(("help", "")), parse(":help"));
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(Some(("time", "(add 1 2)")), parse("  :time   (add 1 2) "));
+    }
+
+    #[test]
+    fn test_parse_empty_name() {
+        assert_eq!(Some(("", "")), parse(":"));
+    }
+
+    #[test]
+    fn test_run_meta_command() {
+        let mut command = MetaCommand::new("echo", "repeat the arguments", |count, args| {
+            *count += 1;
+            Some(args.to_uppercase())
+        });
+        let mut count = 0;
+        assert_eq!("echo", command.name());
+        assert_eq!(Some("HI".to_string()), command.run(&mut count, "hi"));
+        assert_eq!(1, count);
+    }
+}
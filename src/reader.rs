@@ -0,0 +1,309 @@
+use std::fmt;
+
+use pest::error::LineColLocation;
+
+use crate::{SExp, SExpParser};
+
+/// The source text of one complete top-level form.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Form {
+    pub text: String,
+    /// The line of the input the form starts on, counting from 1.
+    pub line: usize,
+    /// The column of `line` the form starts in, counting from 1.
+    pub column: usize,
+}
+
+impl Form {
+    /// Convert a line and column within the form's text into a
+    /// position in the whole input.
+    pub fn position(&self, line: usize, column: usize) -> (usize, usize) {
+        if line <= 1 {
+            (self.line, self.column + column - 1)
+        } else {
+            (self.line + line - 1, column)
+        }
+    }
+
+    pub fn parse(&self) -> Result<SExp, SyntaxError> {
+        SExpParser::parse_line(&self.text).map_err(|error| {
+            let (line, column) = match error.line_col {
+                LineColLocation::Pos(pos) | LineColLocation::Span(pos, _) => pos,
+            };
+            let (line, column) = self.position(line, column);
+            SyntaxError {
+                line,
+                column,
+                message: error.variant.message().into_owned(),
+            }
+        })
+    }
+}
+
+/// Why a form couldn't be parsed, and where in the whole input.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyntaxError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+/// Splits a stream of input lines into complete top-level forms. A
+/// form may span several lines and a line may hold several forms.
+#[derive(Clone, Debug)]
+pub struct FormReader {
+    buffer: String,
+    line: usize,
+    column: usize,
+}
+
+impl Default for FormReader {
+    fn default() -> FormReader {
+        FormReader::new()
+    }
+}
+
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || c == ',' || c == '(' || c == ')' || c == '"' || c == ';'
+}
+
+impl FormReader {
+    pub fn new() -> FormReader {
+        FormReader {
+            buffer: String::new(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    pub fn push_line(&mut self, line: &str) {
+        self.buffer
+            .push_str(line.trim_end_matches(&['\r', '\n'][..]));
+        self.buffer.push('\n');
+    }
+
+    /// True when there is no partially read form waiting for more input.
+    pub fn is_idle(&mut self) -> bool {
+        self.skip_trivia();
+        self.buffer.is_empty()
+    }
+
+    /// Take the next complete form out of the buffer, if there is one.
+    pub fn next_form(&mut self) -> Option<Form> {
+        self.skip_trivia();
+        let end = form_end(&self.buffer)?;
+        Some(self.take(end))
+    }
+
+    /// Take whatever incomplete input is left once the stream has ended.
+    pub fn finish(&mut self) -> Option<Form> {
+        if self.is_idle() {
+            None
+        } else {
+            let len = self.buffer.len();
+            Some(self.take(len))
+        }
+    }
+
+    fn take(&mut self, end: usize) -> Form {
+        let text: String = self.buffer.drain(..end).collect();
+        let (line, column) = (self.line, self.column);
+        match text.rfind('\n') {
+            Some(newline) => {
+                self.line += text.matches('\n').count();
+                self.column = text[newline + 1..].chars().count() + 1;
+            }
+            None => self.column += text.chars().count(),
+        }
+        Form { text, line, column }
+    }
+
+    fn skip_trivia(&mut self) {
+        let end = trivia_end(&self.buffer);
+        self.take(end);
+    }
+}
+
+/// The length of the whitespace and comments at the start of `text`.
+fn trivia_end(text: &str) -> usize {
+    let mut idx = 0;
+    while idx < text.len() {
+        let rest = &text[idx..];
+        if rest.starts_with(";;") {
+            match rest.find('\n') {
+                Some(newline) => idx += newline + 1,
+                None => return idx,
+            }
+        } else {
+            let c = rest.chars().next().unwrap();
+            if c.is_whitespace() || c == ',' {
+                idx += c.len_utf8();
+            } else {
+                break;
+            }
+        }
+    }
+    idx
+}
+
+/// The length of the complete form at the start of `text`, or `None`
+/// if more input is needed to finish it.
+fn form_end(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                if depth <= 1 {
+                    // a stray `)` is passed along on its own to be
+                    // reported as a parse error
+                    return Some(idx + 1);
+                }
+                depth -= 1;
+            }
+            '"' => loop {
+                match chars.next() {
+                    Some((end, '"')) if depth == 0 => return Some(end + 1),
+                    Some((_, '"')) => break,
+                    Some(_) => (),
+                    None => return None,
+                }
+            },
+            ';' if depth > 0 && text[idx..].starts_with(";;") => loop {
+                match chars.next() {
+                    Some((_, '\n')) => break,
+                    Some(_) => (),
+                    None => return None,
+                }
+            },
+            _ if depth == 0 => {
+                while let Some(&(end, c)) = chars.peek() {
+                    if is_delimiter(c) {
+                        return Some(end);
+                    }
+                    chars.next();
+                }
+                return None;
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Every form in `text`, ending with any incomplete input left over.
+pub(crate) fn read_forms(text: &str) -> Vec<Form> {
+    let mut reader = FormReader::new();
+    for line in text.lines() {
+        reader.push_line(line);
+    }
+
+    let mut forms = vec![];
+    while let Some(form) = reader.next_form().or_else(|| reader.finish()) {
+        forms.push(form);
+    }
+    forms
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn form(text: &str, line: usize, column: usize) -> Option<Form> {
+        Some(Form {
+            text: text.into(),
+            line,
+            column,
+        })
+    }
+
+    fn read_all(lines: &[&str]) -> Vec<Form> {
+        let mut reader = FormReader::new();
+        let mut forms = vec![];
+        for line in lines {
+            reader.push_line(line);
+            while let Some(form) = reader.next_form() {
+                forms.push(form);
+            }
+        }
+        forms.extend(reader.finish());
+        forms
+    }
+
+    #[test]
+    fn test_several_forms_on_a_line() {
+        let forms = read_all(&["1 (add 1 2) foo \"bar baz\""]);
+        let texts: Vec<&str> = forms.iter().map(|form| form.text.as_ref()).collect();
+        assert_eq!(vec!["1", "(add 1 2)", "foo", "\"bar baz\""], texts);
+    }
+
+    #[test]
+    fn test_multi_line_form() {
+        let mut reader = FormReader::new();
+        reader.push_line("(def foo 1");
+        assert_eq!(None, reader.next_form());
+        assert!(!reader.is_idle());
+        reader.push_line("  2 mul)");
+        assert_eq!(form("(def foo 1\n  2 mul)", 1, 1), reader.next_form());
+        assert!(reader.is_idle());
+    }
+
+    #[test]
+    fn test_line_numbers() {
+        let forms = read_all(&["1", "", ";; a comment", "(a", "b) c"]);
+        assert_eq!(
+            vec![
+                form("1", 1, 1).unwrap(),
+                form("(a\nb)", 4, 1).unwrap(),
+                form("c", 5, 4).unwrap()
+            ],
+            forms
+        );
+    }
+
+    #[test]
+    fn test_parens_in_strings_and_comments() {
+        let forms = read_all(&["(a \")\" ;; (", "b)"]);
+        assert_eq!(vec![form("(a \")\" ;; (\nb)", 1, 1).unwrap()], forms);
+    }
+
+    #[test]
+    fn test_stray_close_paren() {
+        let forms = read_all(&[") 1"]);
+        assert_eq!(
+            vec![form(")", 1, 1).unwrap(), form("1", 1, 3).unwrap()],
+            forms
+        );
+    }
+
+    #[test]
+    fn test_unfinished_form() {
+        let forms = read_all(&["(a", "(b c)"]);
+        assert_eq!(vec![form("(a\n(b c)\n", 1, 1).unwrap()], forms);
+    }
+
+    #[test]
+    fn test_parse_error_position() {
+        let forms = read_all(&["1 (a", "  \"b)"]);
+        let error = forms[1].parse().unwrap_err();
+        assert_eq!((2, 3), (error.line, error.column));
+    }
+
+    #[test]
+    fn test_position() {
+        let form = form("(a\n b)", 3, 5).unwrap();
+        assert_eq!((3, 6), form.position(1, 2));
+        assert_eq!((4, 2), form.position(2, 2));
+    }
+}
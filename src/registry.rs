@@ -0,0 +1,522 @@
+//! Running several languages from one program.
+//!
+//! Each language crate describes itself with a `Registration`. A
+//! `Registry` collects them and picks one by name or by file extension,
+//! and a `Polyglot` is a language that stands in for whichever of them
+//! is current, so a single `Interpreter` can switch between them with
+//! `:lang`.
+
+use std::fmt;
+use std::path::Path;
+
+use crate::{Interpreter, Interrupt, Language, MetaCommand, SExp};
+
+/// An error from a language whose type has been erased. It prints the
+/// same way the original error does with `{:?}`.
+#[derive(Clone, PartialEq)]
+pub struct ErrorText(String);
+
+impl fmt::Debug for ErrorText {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The parts of `Language` that don't depend on its associated types,
+/// plus the meta-commands that came with it.
+trait Erased {
+    fn name(&self) -> &str;
+    fn eval(&mut self, expr: SExp) -> Result<Option<String>, ErrorText>;
+    fn reset(&mut self);
+    fn set_interrupt(&mut self, interrupt: Interrupt);
+    fn save(&self) -> Vec<SExp>;
+    fn complete(&self, prefix: &str) -> Vec<String>;
+    fn describe(&self, name: &str) -> Option<String>;
+    fn defines(&self, form: &SExp) -> Option<String>;
+    fn take_warnings(&mut self) -> Vec<String>;
+    fn commands(&self) -> Vec<(String, String)>;
+    fn run_command(&mut self, name: &str, args: &str) -> Option<Option<String>>;
+}
+
+struct Wrapped<L> {
+    language: L,
+    commands: Vec<MetaCommand<L>>,
+}
+
+impl<L: Language> Erased for Wrapped<L> {
+    fn name(&self) -> &str {
+        self.language.name()
+    }
+
+    fn eval(&mut self, expr: SExp) -> Result<Option<String>, ErrorText> {
+        match self.language.eval(expr) {
+            Ok(value) => Ok(value.map(|value| value.to_string())),
+            Err(error) => Err(ErrorText(format!("{:?}", error))),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.language.reset();
+    }
+
+    fn set_interrupt(&mut self, interrupt: Interrupt) {
+        self.language.set_interrupt(interrupt);
+    }
+
+    fn save(&self) -> Vec<SExp> {
+        self.language.save()
+    }
+
+    fn complete(&self, prefix: &str) -> Vec<String> {
+        self.language.complete(prefix)
+    }
+
+    fn describe(&self, name: &str) -> Option<String> {
+        self.language.describe(name)
+    }
+
+    fn defines(&self, form: &SExp) -> Option<String> {
+        self.language.defines(form)
+    }
+
+    fn take_warnings(&mut self) -> Vec<String> {
+        self.language.take_warnings()
+    }
+
+    fn commands(&self) -> Vec<(String, String)> {
+        self.commands
+            .iter()
+            .map(|command| (command.name().to_string(), command.help().to_string()))
+            .collect()
+    }
+
+    fn run_command(&mut self, name: &str, args: &str) -> Option<Option<String>> {
+        let command = self.commands.iter_mut().find(|c| c.name() == name)?;
+        Some(command.run(&mut self.language, args))
+    }
+}
+
+/// Any language, with its values and errors turned into text.
+pub struct AnyLanguage(Box<dyn Erased>);
+
+impl AnyLanguage {
+    pub fn new<L: Language + 'static>(language: L) -> AnyLanguage {
+        AnyLanguage::with_commands(language, vec![])
+    }
+
+    /// Wrap `language` along with the meta-commands that only make
+    /// sense for it.
+    pub fn with_commands<L: Language + 'static>(
+        language: L,
+        commands: Vec<MetaCommand<L>>,
+    ) -> AnyLanguage {
+        AnyLanguage(Box::new(Wrapped { language, commands }))
+    }
+
+    /// The names and help of the language's own meta-commands.
+    pub fn commands(&self) -> Vec<(String, String)> {
+        self.0.commands()
+    }
+
+    /// Run one of the language's own meta-commands, or return `None`
+    /// if it doesn't have one called `name`.
+    pub fn run_command(&mut self, name: &str, args: &str) -> Option<Option<String>> {
+        self.0.run_command(name, args)
+    }
+}
+
+impl Language for AnyLanguage {
+    type Value = String;
+    type Error = ErrorText;
+
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    fn eval(&mut self, expr: SExp) -> Result<Option<String>, ErrorText> {
+        self.0.eval(expr)
+    }
+
+    fn reset(&mut self) {
+        self.0.reset();
+    }
+
+    fn set_interrupt(&mut self, interrupt: Interrupt) {
+        self.0.set_interrupt(interrupt);
+    }
+
+    fn save(&self) -> Vec<SExp> {
+        self.0.save()
+    }
+
+    fn complete(&self, prefix: &str) -> Vec<String> {
+        self.0.complete(prefix)
+    }
+
+    fn describe(&self, name: &str) -> Option<String> {
+        self.0.describe(name)
+    }
+
+    fn defines(&self, form: &SExp) -> Option<String> {
+        self.0.defines(form)
+    }
+
+    fn take_warnings(&mut self) -> Vec<String> {
+        self.0.take_warnings()
+    }
+}
+
+/// How a language crate makes itself known to a `Registry`.
+pub struct Registration {
+    name: String,
+    extensions: Vec<String>,
+    make_language: Box<dyn Fn() -> AnyLanguage>,
+}
+
+impl Registration {
+    /// `name` is what `--lang` and `:lang` select the language with, and
+    /// files ending in one of `extensions` are run with it.
+    pub fn new(
+        name: impl Into<String>,
+        extensions: &[&str],
+        make_language: impl Fn() -> AnyLanguage + 'static,
+    ) -> Registration {
+        Registration {
+            name: name.into(),
+            extensions: extensions.iter().map(|ext| ext.to_string()).collect(),
+            make_language: Box::new(make_language),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn extensions(&self) -> &[String] {
+        &self.extensions
+    }
+
+    /// A new instance of the language.
+    pub fn make_language(&self) -> AnyLanguage {
+        (self.make_language)()
+    }
+}
+
+#[derive(Default)]
+pub struct Registry {
+    registrations: Vec<Registration>,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    pub fn register(&mut self, registration: Registration) {
+        self.registrations.push(registration);
+    }
+
+    pub fn registrations(&self) -> &[Registration] {
+        &self.registrations
+    }
+
+    /// The registered name of the language called `name`, ignoring case.
+    pub fn find(&self, name: &str) -> Option<&str> {
+        self.position(name)
+            .map(|idx| self.registrations[idx].name.as_str())
+    }
+
+    /// The registered name of the language that runs `path`, going by
+    /// its extension.
+    pub fn for_path(&self, path: impl AsRef<Path>) -> Option<&str> {
+        let ext = path.as_ref().extension()?.to_str()?;
+        self.registrations
+            .iter()
+            .find(|registration| registration.extensions.iter().any(|e| e == ext))
+            .map(|registration| registration.name.as_str())
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.registrations
+            .iter()
+            .position(|registration| registration.name.eq_ignore_ascii_case(name))
+    }
+}
+
+/// A language that evaluates with whichever registered language is
+/// current. Each language is started the first time it's switched to
+/// and keeps its definitions while another one is current.
+pub struct Polyglot {
+    registry: Registry,
+    languages: Vec<Option<AnyLanguage>>,
+    current: usize,
+    interrupt: Interrupt,
+}
+
+impl Polyglot {
+    /// Start with the language registered as `name`.
+    pub fn new(registry: Registry, name: &str) -> Result<Polyglot, String> {
+        let languages = registry.registrations.iter().map(|_| None).collect();
+        let mut polyglot = Polyglot {
+            registry,
+            languages,
+            current: 0,
+            interrupt: Interrupt::new(),
+        };
+        polyglot.switch(name)?;
+        Ok(polyglot)
+    }
+
+    /// Make the language registered as `name` current.
+    pub fn switch(&mut self, name: &str) -> Result<(), String> {
+        let idx = self
+            .registry
+            .position(name)
+            .ok_or_else(|| format!("Unknown language `{}`, try :lang", name))?;
+        if self.languages[idx].is_none() {
+            let mut language = (self.registry.registrations[idx].make_language)();
+            language.set_interrupt(self.interrupt.clone());
+            self.languages[idx] = Some(language);
+        }
+        self.current = idx;
+        Ok(())
+    }
+
+    fn current(&self) -> &AnyLanguage {
+        self.languages[self.current].as_ref().unwrap()
+    }
+
+    fn current_mut(&mut self) -> &mut AnyLanguage {
+        self.languages[self.current].as_mut().unwrap()
+    }
+
+    /// List the registered languages, marking the current one.
+    pub fn describe_languages(&self) -> String {
+        self.registry
+            .registrations
+            .iter()
+            .enumerate()
+            .map(|(idx, registration)| {
+                let marker = if idx == self.current { "*" } else { " " };
+                format!(
+                    "{} {} (.{})",
+                    marker,
+                    registration.name,
+                    registration.extensions.join(", .")
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// An interpreter for the current language with the
+    /// `meta_commands` added.
+    pub fn into_interpreter(self) -> Interpreter<Polyglot> {
+        let commands = self.meta_commands();
+        let mut interpreter = Interpreter::new(self);
+        for command in commands {
+            interpreter.add_command(command);
+        }
+        interpreter
+    }
+
+    /// A `:lang` command to list or switch languages, and the
+    /// meta-commands of every registered language, which run when their
+    /// language is current.
+    pub fn meta_commands(&self) -> Vec<MetaCommand<Polyglot>> {
+        let mut commands = vec![MetaCommand::new(
+            "lang",
+            "list the languages, or switch to <name>",
+            |polyglot: &mut Polyglot, args: &str| {
+                if args.is_empty() {
+                    return Some(polyglot.describe_languages());
+                }
+                match polyglot.switch(args) {
+                    Ok(()) => Some(format!("Switched to {}", polyglot.name())),
+                    Err(error) => Some(error),
+                }
+            },
+        )];
+
+        for (idx, registration) in self.registry.registrations.iter().enumerate() {
+            let language_commands = match &self.languages[idx] {
+                Some(language) => language.commands(),
+                None => (registration.make_language)().commands(),
+            };
+            for (name, help) in language_commands {
+                if commands.iter().any(|command| command.name() == name) {
+                    continue;
+                }
+                let command_name = name.clone();
+                commands.push(MetaCommand::new(
+                    name,
+                    help,
+                    move |polyglot: &mut Polyglot, args: &str| {
+                        let language = polyglot.current_mut();
+                        match language.run_command(&command_name, args) {
+                            Some(output) => output,
+                            None => Some(format!(
+                                "`:{}` isn't available in {}",
+                                command_name,
+                                language.name()
+                            )),
+                        }
+                    },
+                ));
+            }
+        }
+        commands
+    }
+}
+
+impl Language for Polyglot {
+    type Value = String;
+    type Error = ErrorText;
+
+    fn name(&self) -> &str {
+        self.current().name()
+    }
+
+    fn eval(&mut self, expr: SExp) -> Result<Option<String>, ErrorText> {
+        self.current_mut().eval(expr)
+    }
+
+    fn reset(&mut self) {
+        self.current_mut().reset();
+    }
+
+    fn set_interrupt(&mut self, interrupt: Interrupt) {
+        for language in self.languages.iter_mut().flatten() {
+            language.set_interrupt(interrupt.clone());
+        }
+        self.interrupt = interrupt;
+    }
+
+    fn save(&self) -> Vec<SExp> {
+        self.current().save()
+    }
+
+    fn complete(&self, prefix: &str) -> Vec<String> {
+        self.current().complete(prefix)
+    }
+
+    fn describe(&self, name: &str) -> Option<String> {
+        self.current().describe(name)
+    }
+
+    fn defines(&self, form: &SExp) -> Option<String> {
+        self.current().defines(form)
+    }
+
+    fn take_warnings(&mut self) -> Vec<String> {
+        self.current_mut().take_warnings()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::test_support::{Adder, Buffer};
+
+    fn adder(name: &'static str) -> AnyLanguage {
+        let total = MetaCommand::new("total", "the running total", |adder: &mut Adder, _| {
+            Some(adder.total.to_string())
+        });
+        AnyLanguage::with_commands(Adder::named(name), vec![total])
+    }
+
+    fn registry() -> Registry {
+        let mut registry = Registry::new();
+        registry.register(Registration::new("one", &["one", "1"], || adder("One")));
+        registry.register(Registration::new("two", &["two"], || adder("Two")));
+        registry
+    }
+
+    #[test]
+    fn test_find() {
+        let registry = registry();
+        assert_eq!(Some("two"), registry.find("TWO"));
+        assert_eq!(None, registry.find("three"));
+    }
+
+    #[test]
+    fn test_for_path() {
+        let registry = registry();
+        assert_eq!(Some("one"), registry.for_path("dir/script.1"));
+        assert_eq!(Some("two"), registry.for_path("script.two"));
+        assert_eq!(None, registry.for_path("script.txt"));
+        assert_eq!(None, registry.for_path("script"));
+    }
+
+    #[test]
+    fn test_any_language_errors_print_like_the_original() {
+        let mut language = adder("One");
+        let error = language.eval(SExp::symbol("oops")).unwrap_err();
+        assert_eq!("\"NotANumber\"", format!("{:?}", error));
+    }
+
+    #[test]
+    fn test_switch_keeps_state() {
+        let mut polyglot = Polyglot::new(registry(), "one").unwrap();
+        assert_eq!(Ok(Some("1".into())), polyglot.eval(SExp::Integer(1)));
+        polyglot.switch("two").unwrap();
+        assert_eq!("Two", polyglot.name());
+        assert_eq!(Ok(Some("2".into())), polyglot.eval(SExp::Integer(2)));
+        polyglot.switch("one").unwrap();
+        assert_eq!(
+            Some(Some("1".into())),
+            polyglot.current_mut().run_command("total", "")
+        );
+    }
+
+    #[test]
+    fn test_switch_unknown() {
+        let mut polyglot = Polyglot::new(registry(), "one").unwrap();
+        assert!(polyglot.switch("three").is_err());
+        assert_eq!("One", polyglot.name());
+        assert!(Polyglot::new(registry(), "three").is_err());
+    }
+
+    #[test]
+    fn test_lang_command() {
+        let input = "1\n:lang two\n2\n:total\n:lang one\n:total\n:lang three\n";
+        let output = Buffer::default();
+
+        let polyglot = Polyglot::new(registry(), "one").unwrap();
+        let commands = polyglot.meta_commands();
+        let mut interpreter = Interpreter::builder(polyglot)
+            .banner("")
+            .history(crate::HistoryFile::Disabled)
+            .image(None)
+            .init(crate::InitFile::Disabled)
+            .input(input.as_bytes())
+            .output(output.clone())
+            .build();
+        for command in commands {
+            interpreter.add_command(command);
+        }
+        interpreter.run();
+
+        assert_eq!(
+            "one> 1\n\
+             one> Switched to Two\n\
+             two> 2\n\
+             two> 2\n\
+             two> Switched to One\n\
+             one> 1\n\
+             one> Unknown language `three`, try :lang\n\
+             one> ",
+            output.text()
+        );
+    }
+
+    #[test]
+    fn test_describe_languages() {
+        let polyglot = Polyglot::new(registry(), "two").unwrap();
+        assert_eq!(
+            "  one (.one, .1)\n* two (.two)",
+            polyglot.describe_languages()
+        );
+    }
+}
@@ -0,0 +1,327 @@
+//! A JSON-RPC 2.0 server for evaluating code without a terminal.
+//!
+//! Requests and responses are JSON objects, one per line. The methods
+//! are:
+//!
+//! - `eval {"code": string}` evaluates every form in `code` and
+//!   returns `{"values": [string | null]}`, with `null` for forms that
+//!   produce no value. If the language warned about any of the forms
+//!   the result also has `"warnings": [string]`.
+//! - `complete {"prefix": string}` returns the matching names.
+//! - `describe {"name": string}` returns a description or `null`.
+//! - `reset` discards all definitions.
+//! - `shutdown` stops the server after responding.
+//!
+//! Code that doesn't parse fails with `SYNTAX_ERROR` and
+//! `{"line", "column", "message"}` as the error data. A form that fails
+//! to evaluate stops the evaluation with `EVAL_ERROR` and
+//! `{"error": string, "values": [...]}` holding the values produced by
+//! the forms before it.
+
+use std::io::{self, BufRead, Write};
+
+use serde_json::{json, Value};
+
+use crate::{FormReader, Language, SExp};
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const SYNTAX_ERROR: i64 = -32001;
+pub const EVAL_ERROR: i64 = -32002;
+pub const INTERRUPTED: i64 = -32003;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct RpcError {
+    code: i64,
+    message: String,
+    data: Option<Value>,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> RpcError {
+        RpcError {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    fn with_data(code: i64, message: impl Into<String>, data: Value) -> RpcError {
+        RpcError {
+            code,
+            message: message.into(),
+            data: Some(data),
+        }
+    }
+
+    fn into_response(self, id: Value) -> Value {
+        let mut error = json!({ "code": self.code, "message": self.message });
+        if let Some(data) = self.data {
+            error["data"] = data;
+        }
+        json!({ "jsonrpc": "2.0", "id": id, "error": error })
+    }
+}
+
+/// Answer requests read from `input` until `shutdown` is called or the
+/// input ends.
+pub fn serve<L: Language>(
+    language: &mut L,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (response, shutdown) = handle(language, &line);
+        if let Some(response) = response {
+            writeln!(output, "{}", response)?;
+            output.flush()?;
+        }
+        if shutdown {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// A request that was successfully decoded.
+pub(crate) struct Request {
+    pub id: Option<Value>,
+    pub method: String,
+    pub params: Value,
+}
+
+impl Request {
+    /// Decode a request, or produce the error response explaining why
+    /// it couldn't be decoded.
+    pub fn parse(line: &str) -> Result<Request, Value> {
+        let request: Value = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(error) => {
+                let error = RpcError::new(PARSE_ERROR, error.to_string());
+                return Err(error.into_response(Value::Null));
+            }
+        };
+
+        let id = request.get("id").cloned();
+        let method = match request.get("method").and_then(Value::as_str) {
+            Some(method) => method.to_string(),
+            None => {
+                let error = RpcError::new(INVALID_REQUEST, "missing method");
+                return Err(error.into_response(id.unwrap_or(Value::Null)));
+            }
+        };
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+        Ok(Request { id, method, params })
+    }
+
+    /// The response to send for `result`, unless this is a notification.
+    pub fn respond(&self, result: Result<Value, RpcError>) -> Option<Value> {
+        self.id.clone().map(|id| match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(error) => error.into_response(id),
+        })
+    }
+}
+
+/// Answer one request, returning the response (if the request wasn't a
+/// notification) and whether the server should stop.
+fn handle<L: Language>(language: &mut L, line: &str) -> (Option<Value>, bool) {
+    match Request::parse(line) {
+        Ok(request) => {
+            let result = dispatch(language, &request.method, &request.params);
+            (request.respond(result), request.method == "shutdown")
+        }
+        Err(response) => (Some(response), false),
+    }
+}
+
+pub(crate) fn dispatch<L: Language>(
+    language: &mut L,
+    method: &str,
+    params: &Value,
+) -> Result<Value, RpcError> {
+    match method {
+        "eval" => eval(language, string_param(params, "code")?),
+        "complete" => {
+            let prefix = params.get("prefix").and_then(Value::as_str).unwrap_or("");
+            Ok(json!(language.complete(prefix)))
+        }
+        "describe" => Ok(json!(language.describe(string_param(params, "name")?))),
+        "reset" => {
+            language.reset();
+            Ok(Value::Null)
+        }
+        "shutdown" => Ok(Value::Null),
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("unknown method `{}`", method),
+        )),
+    }
+}
+
+fn string_param<'a>(params: &'a Value, name: &str) -> Result<&'a str, RpcError> {
+    params.get(name).and_then(Value::as_str).ok_or_else(|| {
+        RpcError::new(
+            INVALID_PARAMS,
+            format!("missing string parameter `{}`", name),
+        )
+    })
+}
+
+fn eval<L: Language>(language: &mut L, code: &str) -> Result<Value, RpcError> {
+    let mut reader = FormReader::new();
+    for line in code.lines() {
+        reader.push_line(line);
+    }
+
+    let mut exprs: Vec<SExp> = vec![];
+    while let Some(form) = reader.next_form().or_else(|| reader.finish()) {
+        let expr = form.parse().map_err(|error| {
+            let data = json!({
+                "line": error.line,
+                "column": error.column,
+                "message": error.message,
+            });
+            RpcError::with_data(SYNTAX_ERROR, "invalid input", data)
+        })?;
+        exprs.push(expr);
+    }
+
+    let mut values = vec![];
+    let mut warnings = vec![];
+    for expr in exprs {
+        let result = language.eval(expr);
+        warnings.extend(language.take_warnings());
+        match result {
+            Ok(value) => values.push(value.map(|value| value.to_string())),
+            Err(error) => {
+                let data = json!({ "error": format!("{:?}", error), "values": values });
+                return Err(RpcError::with_data(EVAL_ERROR, "evaluation failed", data));
+            }
+        }
+    }
+    if warnings.is_empty() {
+        Ok(json!({ "values": values }))
+    } else {
+        Ok(json!({ "values": values, "warnings": warnings }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::test_support::Adder;
+
+    fn serve_lines(lines: &[&str]) -> Vec<Value> {
+        let input = lines.join("\n");
+        let mut output = vec![];
+        serve(&mut Adder::new(), input.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    fn request(id: i64, method: &str, params: Value) -> String {
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }).to_string()
+    }
+
+    #[test]
+    fn test_eval() {
+        let responses = serve_lines(&[&request(1, "eval", json!({"code": "(1 2)\nclear\n(3)"}))]);
+        assert_eq!(
+            vec![json!({"jsonrpc": "2.0", "id": 1, "result": {"values": ["3", null, "3"]}})],
+            responses
+        );
+    }
+
+    #[test]
+    fn test_eval_warnings() {
+        let responses = serve_lines(&[&request(1, "eval", json!({"code": "() 1"}))]);
+        assert_eq!(
+            json!({"values": ["0", "1"], "warnings": ["() adds nothing"]}),
+            responses[0]["result"]
+        );
+    }
+
+    #[test]
+    fn test_eval_error() {
+        let responses = serve_lines(&[&request(1, "eval", json!({"code": "(1) (a)"}))]);
+        assert_eq!(
+            json!({"code": EVAL_ERROR, "message": "evaluation failed", "data": {
+                "error": "\"NotANumber\"",
+                "values": ["1"],
+            }}),
+            responses[0]["error"]
+        );
+    }
+
+    #[test]
+    fn test_eval_syntax_error() {
+        let responses = serve_lines(&[&request(1, "eval", json!({"code": "(1)\n  )"}))]);
+        let error = &responses[0]["error"];
+        assert_eq!(json!(SYNTAX_ERROR), error["code"]);
+        assert_eq!(json!(2), error["data"]["line"]);
+        assert_eq!(json!(3), error["data"]["column"]);
+    }
+
+    #[test]
+    fn test_complete_and_reset() {
+        let responses = serve_lines(&[
+            &request(1, "eval", json!({"code": "(5)"})),
+            &request(2, "reset", Value::Null),
+            &request(3, "eval", json!({"code": "total"})),
+            &request(4, "complete", json!({"prefix": "c"})),
+        ]);
+        assert_eq!(json!({"values": ["0"]}), responses[2]["result"]);
+        assert_eq!(json!(["clear"]), responses[3]["result"]);
+    }
+
+    #[test]
+    fn test_shutdown_stops_serving() {
+        let responses = serve_lines(&[
+            &request(1, "shutdown", Value::Null),
+            &request(2, "eval", json!({"code": "(1)"})),
+        ]);
+        assert_eq!(1, responses.len());
+        assert_eq!(Value::Null, responses[0]["result"]);
+    }
+
+    #[test]
+    fn test_notification_has_no_response() {
+        let notification = json!({"jsonrpc": "2.0", "method": "reset"}).to_string();
+        assert!(serve_lines(&[&notification]).is_empty());
+    }
+
+    #[test]
+    fn test_protocol_errors() {
+        let responses = serve_lines(&[
+            "not json",
+            &json!({"jsonrpc": "2.0", "id": 1}).to_string(),
+            &request(2, "launch", Value::Null),
+            &request(3, "describe", json!({})),
+        ]);
+        let codes: Vec<Value> = responses
+            .iter()
+            .map(|r| r["error"]["code"].clone())
+            .collect();
+        assert_eq!(
+            vec![
+                json!(PARSE_ERROR),
+                json!(INVALID_REQUEST),
+                json!(METHOD_NOT_FOUND),
+                json!(INVALID_PARAMS)
+            ],
+            codes
+        );
+    }
+}
@@ -0,0 +1,378 @@
+//! A REPL server that many clients can attach to over TCP or Unix
+//! sockets.
+//!
+//! Every connection is a session with its own evaluation loop, speaking
+//! the line-delimited JSON-RPC protocol from the `rpc` module. On top of
+//! those methods a session understands:
+//!
+//! - `interrupt` stops the request being evaluated, if the language
+//!   checks its `Interrupt`, and abandons the requests the session has
+//!   queued but not started yet. Those fail with `INTERRUPTED`.
+//! - `close` waits for the queued requests, responds, and ends the
+//!   session. `shutdown` is treated the same way, since one client
+//!   shouldn't stop the server for the others.
+//!
+//! Sessions either share one language or each get a fresh one, see
+//! `Server::shared` and `Server::per_session`.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+
+use serde_json::Value;
+
+use crate::rpc::{self, Request, RpcError, INTERRUPTED};
+use crate::{Interrupt, Language};
+
+type Factory<L> = dyn Fn() -> L + Send + Sync;
+
+enum State<L> {
+    Shared(Arc<Mutex<L>>, Interrupt),
+    PerSession(Arc<Factory<L>>),
+}
+
+pub struct Server<L> {
+    state: State<L>,
+}
+
+impl<L> Clone for Server<L> {
+    fn clone(&self) -> Server<L> {
+        let state = match &self.state {
+            State::Shared(language, interrupt) => {
+                State::Shared(language.clone(), interrupt.clone())
+            }
+            State::PerSession(make_language) => State::PerSession(make_language.clone()),
+        };
+        Server { state }
+    }
+}
+
+fn send(output: &Mutex<impl Write>, response: &Value) {
+    let mut output = output.lock().unwrap_or_else(PoisonError::into_inner);
+    // a client that hung up has nobody left to tell
+    let _ = writeln!(output, "{}", response).and_then(|_| output.flush());
+}
+
+/// A connection that was just accepted, split into the half the
+/// session reads and the half it writes, or `None` after reporting why
+/// it couldn't be. A failed connection only drops that client, so a
+/// server keeps accepting others.
+fn accepted<S>(
+    stream: io::Result<S>,
+    try_clone: impl FnOnce(&S) -> io::Result<S>,
+) -> Option<(S, S)> {
+    match stream.and_then(|stream| Ok((try_clone(&stream)?, stream))) {
+        Ok(halves) => Some(halves),
+        Err(error) => {
+            eprintln!("error: accepting a connection: {}", error);
+            None
+        }
+    }
+}
+
+impl<L> Server<L>
+where
+    L: Language + Send + 'static,
+{
+    /// Every session evaluates in the same language. Interrupting any
+    /// session stops whichever evaluation is running.
+    pub fn shared(mut language: L) -> Server<L> {
+        let interrupt = Interrupt::new();
+        language.set_interrupt(interrupt.clone());
+        Server {
+            state: State::Shared(Arc::new(Mutex::new(language)), interrupt),
+        }
+    }
+
+    /// Every session starts with its own language from `make_language`.
+    pub fn per_session(make_language: impl Fn() -> L + Send + Sync + 'static) -> Server<L> {
+        Server {
+            state: State::PerSession(Arc::new(make_language)),
+        }
+    }
+
+    pub fn listen_tcp(&self, address: impl ToSocketAddrs) -> io::Result<()> {
+        self.serve_tcp(TcpListener::bind(address)?)
+    }
+
+    /// Accept connections forever, running each session on its own
+    /// thread.
+    pub fn serve_tcp(&self, listener: TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            if let Some((input, stream)) = accepted(stream, TcpStream::try_clone) {
+                let server = self.clone();
+                thread::spawn(move || server.serve_connection(input, stream));
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    pub fn listen_unix(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.serve_unix(UnixListener::bind(path)?)
+    }
+
+    /// Accept connections forever, running each session on its own
+    /// thread.
+    #[cfg(unix)]
+    pub fn serve_unix(&self, listener: UnixListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            if let Some((input, stream)) = accepted(stream, UnixStream::try_clone) {
+                let server = self.clone();
+                thread::spawn(move || server.serve_connection(input, stream));
+            }
+        }
+        Ok(())
+    }
+
+    /// Run one session until the client closes it or disconnects.
+    pub fn serve_connection(&self, input: impl Read, output: impl Write + Send + 'static) {
+        let (language, interrupt) = match &self.state {
+            State::Shared(language, interrupt) => (language.clone(), interrupt.clone()),
+            State::PerSession(make_language) => {
+                let mut language = make_language();
+                let interrupt = Interrupt::new();
+                language.set_interrupt(interrupt.clone());
+                (Arc::new(Mutex::new(language)), interrupt)
+            }
+        };
+        let output = Arc::new(Mutex::new(output));
+        // requests queued before the latest interrupt are abandoned
+        let generation = Arc::new(AtomicUsize::new(0));
+        let (requests, queue) = mpsc::channel::<(usize, Request)>();
+
+        let worker = {
+            let output = output.clone();
+            let generation = generation.clone();
+            let interrupt = interrupt.clone();
+            thread::spawn(move || {
+                for (queued_in, request) in queue {
+                    let mut language = language.lock().unwrap_or_else(PoisonError::into_inner);
+                    // clear before checking, so an interrupt that comes
+                    // after the check still stops this request
+                    interrupt.clear();
+                    let result = if queued_in != generation.load(Ordering::SeqCst) {
+                        Err(RpcError::new(INTERRUPTED, "interrupted"))
+                    } else {
+                        rpc::dispatch(&mut *language, &request.method, &request.params)
+                    };
+                    drop(language);
+                    if let Some(response) = request.respond(result) {
+                        send(&output, &response);
+                    }
+                }
+            })
+        };
+
+        let mut closing = None;
+        for line in BufReader::new(input).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let request = match Request::parse(&line) {
+                Ok(request) => request,
+                Err(response) => {
+                    send(&output, &response);
+                    continue;
+                }
+            };
+            match request.method.as_str() {
+                "interrupt" => {
+                    // before triggering, since the worker clears the
+                    // flag before reading the generation
+                    generation.fetch_add(1, Ordering::SeqCst);
+                    interrupt.trigger();
+                    if let Some(response) = request.respond(Ok(Value::Null)) {
+                        send(&output, &response);
+                    }
+                }
+                "close" | "shutdown" => {
+                    closing = Some(request);
+                    break;
+                }
+                _ => {
+                    let _ = requests.send((generation.load(Ordering::SeqCst), request));
+                }
+            }
+        }
+
+        drop(requests);
+        let _ = worker.join();
+        if let Some(response) = closing.and_then(|request| request.respond(Ok(Value::Null))) {
+            send(&output, &response);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use serde_json::json;
+
+    use crate::rpc::EVAL_ERROR;
+    use crate::test_support::Adder;
+
+    struct Client<S> {
+        input: BufReader<S>,
+        output: S,
+        next_id: i64,
+    }
+
+    impl<S: Read + Write> Client<S> {
+        fn new(input: S, output: S) -> Client<S> {
+            Client {
+                input: BufReader::new(input),
+                output,
+                next_id: 0,
+            }
+        }
+
+        fn send(&mut self, method: &str, params: Value) {
+            self.next_id += 1;
+            let request = json!({
+                "jsonrpc": "2.0",
+                "id": self.next_id,
+                "method": method,
+                "params": params,
+            });
+            writeln!(self.output, "{}", request).unwrap();
+        }
+
+        fn receive(&mut self) -> Value {
+            let mut line = String::new();
+            self.input.read_line(&mut line).unwrap();
+            serde_json::from_str(&line).unwrap()
+        }
+
+        fn call(&mut self, method: &str, params: Value) -> Value {
+            self.send(method, params);
+            self.receive()
+        }
+
+        fn eval(&mut self, code: &str) -> Value {
+            self.call("eval", json!({ "code": code }))["result"]["values"].clone()
+        }
+    }
+
+    fn start_tcp(server: Server<Adder>) -> impl FnMut() -> Client<TcpStream> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || server.serve_tcp(listener));
+        move || {
+            let stream = TcpStream::connect(address).unwrap();
+            Client::new(stream.try_clone().unwrap(), stream)
+        }
+    }
+
+    #[test]
+    fn test_per_session_state() {
+        let mut connect = start_tcp(Server::per_session(Adder::new));
+        let mut first = connect();
+        let mut second = connect();
+        first.eval("7");
+        second.eval("9");
+        assert_eq!(json!(["7"]), first.eval("total"));
+        assert_eq!(json!(["9"]), second.eval("total"));
+    }
+
+    #[test]
+    fn test_shared_state() {
+        let mut connect = start_tcp(Server::shared(Adder::new()));
+        let mut first = connect();
+        let mut second = connect();
+        first.eval("7");
+        assert_eq!(json!(["7"]), second.eval("total"));
+        second.call("reset", Value::Null);
+        assert_eq!(json!(["0"]), first.eval("total"));
+    }
+
+    #[test]
+    fn test_close() {
+        let mut connect = start_tcp(Server::per_session(Adder::new));
+        let mut client = connect();
+        client.send("eval", json!({ "code": "5" }));
+        client.send("close", Value::Null);
+        assert_eq!(json!(["5"]), client.receive()["result"]["values"]);
+        assert_eq!(Value::Null, client.receive()["result"]);
+
+        let mut line = String::new();
+        assert_eq!(0, client.input.read_line(&mut line).unwrap());
+    }
+
+    #[test]
+    fn test_interrupt_abandons_queued_requests() {
+        let (started, wait_for_start) = mpsc::channel();
+        let (release, released) = mpsc::channel();
+        let mut language = Adder::new();
+        language.started = Some(started);
+        language.release = Some(released);
+
+        let mut connect = start_tcp(Server::shared(language));
+        let mut client = connect();
+        client.send("eval", json!({ "code": "block" }));
+        wait_for_start.recv().unwrap();
+        client.send("eval", json!({ "code": "total" }));
+
+        assert_eq!(Value::Null, client.call("interrupt", Value::Null)["result"]);
+        release.send(()).unwrap();
+        assert_eq!(json!(["0"]), client.receive()["result"]["values"]);
+        assert_eq!(json!(INTERRUPTED), client.receive()["error"]["code"]);
+        assert_eq!(json!(["0"]), client.eval("total"));
+    }
+
+    #[test]
+    fn test_interrupt_stops_running_eval() {
+        let (started, wait_for_start) = mpsc::channel();
+        let mut language = Adder::new();
+        language.started = Some(started);
+
+        let mut connect = start_tcp(Server::shared(language));
+        let mut client = connect();
+        client.send("eval", json!({ "code": "spin" }));
+        wait_for_start.recv().unwrap();
+        client.send("interrupt", Value::Null);
+
+        let mut responses = [client.receive(), client.receive()];
+        responses.sort_by_key(|response| response["id"].as_i64());
+        assert_eq!(json!(EVAL_ERROR), responses[0]["error"]["code"]);
+        assert_eq!(Value::Null, responses[1]["result"]);
+        assert_eq!(json!(["0"]), client.eval("total"));
+    }
+
+    #[test]
+    fn test_failed_connection_is_dropped() {
+        let aborted = || io::Error::new(io::ErrorKind::ConnectionAborted, "aborted");
+        assert_eq!(None, accepted(Err(aborted()), |_: &i32| Ok(1)));
+        assert_eq!(None, accepted(Ok(1), |_| Err(aborted())));
+        assert_eq!(Some((2, 1)), accepted(Ok(1), |_| Ok(2)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_socket() {
+        let path = std::env::temp_dir().join(format!("dcpl-server-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = Server::per_session(Adder::new);
+        thread::spawn(move || server.serve_unix(listener));
+
+        let stream = UnixStream::connect(&path).unwrap();
+        let mut client = Client::new(stream.try_clone().unwrap(), stream);
+        client.eval("3");
+        assert_eq!(json!(["3"]), client.eval("total"));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
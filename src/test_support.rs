@@ -0,0 +1,151 @@
+//! A toy language and an output buffer shared by the tests.
+
+use std::io::{self, Write};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::{Interrupt, Language, SExp};
+
+/// Keeps a running total of the integers it's given. A list adds up
+/// its elements first, and `()` warns that it adds nothing. `total`
+/// gives the total and `clear` zeroes it without a value. `block` waits
+/// until the test releases it and `spin` runs until it's interrupted,
+/// both telling the test once they've started. Anything else is an
+/// error.
+pub(crate) struct Adder {
+    name: &'static str,
+    pub total: i128,
+    warnings: Vec<String>,
+    interrupt: Interrupt,
+    pub started: Option<Sender<()>>,
+    pub release: Option<Receiver<()>>,
+}
+
+const NAMES: [&str; 4] = ["block", "clear", "spin", "total"];
+
+impl Adder {
+    pub fn new() -> Adder {
+        Adder::named("Adder")
+    }
+
+    pub fn named(name: &'static str) -> Adder {
+        Adder {
+            name,
+            total: 0,
+            warnings: vec![],
+            interrupt: Interrupt::new(),
+            started: None,
+            release: None,
+        }
+    }
+
+    fn start(&self) {
+        if let Some(started) = &self.started {
+            started.send(()).unwrap();
+        }
+    }
+}
+
+impl Default for Adder {
+    fn default() -> Adder {
+        Adder::new()
+    }
+}
+
+impl Language for Adder {
+    type Value = i128;
+    type Error = &'static str;
+
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn eval(&mut self, expr: SExp) -> Result<Option<i128>, &'static str> {
+        let amount = match expr {
+            SExp::Integer(value) => value,
+            SExp::List(exprs) => {
+                if exprs.is_empty() {
+                    self.warnings.push("() adds nothing".to_string());
+                }
+                let mut sum = 0;
+                for expr in exprs {
+                    sum += expr.into_integer().ok_or("NotANumber")?;
+                }
+                sum
+            }
+            SExp::Symbol(name) => match name.as_str() {
+                "total" => return Ok(Some(self.total)),
+                "clear" => {
+                    self.total = 0;
+                    return Ok(None);
+                }
+                "block" => {
+                    self.start();
+                    self.release.as_ref().unwrap().recv().unwrap();
+                    return Ok(Some(self.total));
+                }
+                "spin" => {
+                    self.start();
+                    while !self.interrupt.is_triggered() {
+                        thread::yield_now();
+                    }
+                    return Err("Interrupted");
+                }
+                _ => return Err("NotANumber"),
+            },
+            _ => return Err("NotANumber"),
+        };
+        self.total += amount;
+        Ok(Some(self.total))
+    }
+
+    fn reset(&mut self) {
+        self.total = 0;
+    }
+
+    fn set_interrupt(&mut self, interrupt: Interrupt) {
+        self.interrupt = interrupt;
+    }
+
+    fn complete(&self, prefix: &str) -> Vec<String> {
+        NAMES
+            .iter()
+            .filter(|name| name.starts_with(prefix))
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    fn describe(&self, name: &str) -> Option<String> {
+        if name == "total" {
+            Some(format!("total = {}", self.total))
+        } else {
+            None
+        }
+    }
+
+    fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+}
+
+/// A `Write` that tests can read back after handing it over.
+#[derive(Clone, Default)]
+pub(crate) struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Buffer {
+    pub fn text(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
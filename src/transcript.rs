@@ -0,0 +1,375 @@
+//! Golden tests for languages, written as REPL transcripts.
+//!
+//! A transcript is a source file whose forms are each followed by the
+//! output they are expected to produce, written as comments:
+//!
+//! ```text
+//! ;; squaring a number
+//! (def sq 1 1 nget mul)
+//! (sq 4)
+//! ;;=> 16
+//! (sq)
+//! ;;!! ProgramError(WrongNumberOfArgs { expected: 1, actual: 0 })
+//! ```
+//!
+//! `;;=>` lines hold the values a form produces and `;;!!` lines the
+//! errors. Any other comment or blank line is left alone.
+//!
+//! An `Interpreter` can also write a transcript of a live session with
+//! `--record` or `:record`, which replays the same way.
+
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::{FormReader, Language, SExp, SExpParser};
+
+/// The environment variable that makes `assert_transcripts` rewrite the
+/// expected output instead of comparing against it.
+pub const BLESS_VAR: &str = "DCPL_BLESS";
+
+const VALUE_PREFIX: &str = ";;=>";
+const ERROR_PREFIX: &str = ";;!!";
+
+#[derive(Clone, Debug, PartialEq)]
+enum Entry {
+    Comment(String),
+    Exchange {
+        line: usize,
+        source: Vec<String>,
+        output: Vec<String>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transcript {
+    entries: Vec<Entry>,
+}
+
+fn is_output(line: &str) -> bool {
+    line.starts_with(VALUE_PREFIX) || line.starts_with(ERROR_PREFIX)
+}
+
+fn is_trivia(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with(";;")
+}
+
+/// The output lines of a value or error, as they appear in a transcript.
+fn output_lines(prefix: &str, text: &str) -> Vec<String> {
+    text.lines()
+        .map(|line| format!("{} {}", prefix, line))
+        .collect()
+}
+
+pub(crate) fn value_lines(text: &str) -> Vec<String> {
+    output_lines(VALUE_PREFIX, text)
+}
+
+pub(crate) fn error_lines(text: &str) -> Vec<String> {
+    output_lines(ERROR_PREFIX, text)
+}
+
+/// A transcript being written to a file as a session goes on. Each
+/// exchange is written as soon as it happens, so nothing is lost if the
+/// session ends abruptly.
+pub(crate) struct Recording {
+    path: PathBuf,
+    file: File,
+}
+
+impl Recording {
+    pub fn create(path: &Path, language_name: &str) -> Result<Recording, String> {
+        let mut file = File::create(path)
+            .map_err(|error| format!("could not write {}: {}", path.display(), error))?;
+        writeln!(file, ";; {} session transcript", language_name)
+            .map_err(|error| format!("could not write {}: {}", path.display(), error))?;
+        Ok(Recording {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write the source of a form and the output it produced.
+    pub fn record(&mut self, source: &str, output: &[String]) -> Result<(), String> {
+        let mut text = String::new();
+        for line in source.lines().chain(output.iter().map(String::as_str)) {
+            text.push_str(line);
+            text.push('\n');
+        }
+        self.file
+            .write_all(text.as_bytes())
+            .map_err(|error| format!("could not write {}: {}", self.path.display(), error))
+    }
+}
+
+impl Transcript {
+    pub fn parse(text: &str) -> Transcript {
+        let mut entries = vec![];
+        let mut reader = FormReader::new();
+
+        for (idx, line) in text.lines().enumerate() {
+            let open_form = !reader.is_idle();
+            match entries.last_mut() {
+                Some(Entry::Exchange { source, .. }) if open_form => {
+                    source.push(line.to_string());
+                    reader.push_line(line);
+                }
+                Some(Entry::Exchange { output, .. }) if is_output(line) => {
+                    output.push(line.to_string())
+                }
+                _ if is_trivia(line) => entries.push(Entry::Comment(line.to_string())),
+                _ => {
+                    entries.push(Entry::Exchange {
+                        line: idx + 1,
+                        source: vec![line.to_string()],
+                        output: vec![],
+                    });
+                    reader.push_line(line);
+                }
+            }
+            while reader.next_form().is_some() {}
+        }
+
+        Transcript { entries }
+    }
+
+    /// Evaluate the transcript's inputs with `language`, producing a
+    /// transcript of what it actually output.
+    pub fn replay<L: Language>(&self, language: &mut L) -> Transcript {
+        self.replay_with(|sexp| match language.eval(sexp) {
+            Ok(Some(value)) => value_lines(&value.to_string()),
+            Ok(None) => vec![],
+            Err(error) => error_lines(&format!("{:?}", error)),
+        })
+    }
+
+    /// Evaluate the transcript's inputs with a closure that returns the
+    /// text the REPL would print.
+    pub fn replay_fn(&self, mut interpret: impl FnMut(SExp) -> Option<String>) -> Transcript {
+        self.replay_with(|sexp| match interpret(sexp) {
+            Some(output) => value_lines(&output),
+            None => vec![],
+        })
+    }
+
+    fn replay_with(&self, mut eval: impl FnMut(SExp) -> Vec<String>) -> Transcript {
+        let entries = self
+            .entries
+            .iter()
+            .map(|entry| match entry {
+                Entry::Comment(_) => entry.clone(),
+                Entry::Exchange { line, source, .. } => {
+                    let mut reader = FormReader::new();
+                    let mut output = vec![];
+                    for source_line in source {
+                        reader.push_line(source_line);
+                    }
+                    while let Some(form) = reader.next_form().or_else(|| reader.finish()) {
+                        match SExpParser::parse_line(&form.text) {
+                            Ok(sexp) => output.extend(eval(sexp)),
+                            Err(error) => output.extend(error_lines(&format!(
+                                "invalid input: {}",
+                                error.variant.message()
+                            ))),
+                        }
+                    }
+                    Entry::Exchange {
+                        line: *line,
+                        source: source.clone(),
+                        output,
+                    }
+                }
+            })
+            .collect();
+        Transcript { entries }
+    }
+
+    /// Describe where `actual` differs from the output expected here.
+    pub fn diff(&self, actual: &Transcript) -> Option<String> {
+        let mut report = String::new();
+        for (expected, actual) in self.entries.iter().zip(&actual.entries) {
+            if let (
+                Entry::Exchange {
+                    line,
+                    source,
+                    output: expected,
+                },
+                Entry::Exchange { output: actual, .. },
+            ) = (expected, actual)
+            {
+                if expected != actual {
+                    report.push_str(&format!("line {}: {}\n", line, source.join("\n")));
+                    for line in expected {
+                        report.push_str(&format!("- {}\n", line));
+                    }
+                    for line in actual {
+                        report.push_str(&format!("+ {}\n", line));
+                    }
+                }
+            }
+        }
+
+        if report.is_empty() {
+            None
+        } else {
+            Some(report)
+        }
+    }
+}
+
+impl fmt::Display for Transcript {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        for entry in &self.entries {
+            match entry {
+                Entry::Comment(line) => writeln!(f, "{}", line)?,
+                Entry::Exchange { source, output, .. } => {
+                    for line in source.iter().chain(output) {
+                        writeln!(f, "{}", line)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Replay every `.transcript` file in `dir` against a fresh language
+/// from `make_language`, and panic describing every difference from
+/// the expected output. When `DCPL_BLESS` is set the files are
+/// rewritten with the actual output instead.
+pub fn assert_transcripts<L: Language>(
+    dir: impl AsRef<Path>,
+    mut make_language: impl FnMut() -> L,
+) {
+    let dir = dir.as_ref();
+    let bless = env::var_os(BLESS_VAR).is_some();
+
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap_or_else(|error| panic!("could not read {}: {}", dir.display(), error))
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "transcript"))
+        .collect();
+    paths.sort();
+    assert!(
+        !paths.is_empty(),
+        "no transcripts found in {}",
+        dir.display()
+    );
+
+    let mut failures = String::new();
+    for path in paths {
+        let text = fs::read_to_string(&path)
+            .unwrap_or_else(|error| panic!("could not read {}: {}", path.display(), error));
+        let expected = Transcript::parse(&text);
+        let actual = expected.replay(&mut make_language());
+
+        if bless {
+            let actual_text = actual.to_string();
+            if actual_text != text {
+                fs::write(&path, actual_text).unwrap_or_else(|error| {
+                    panic!("could not write {}: {}", path.display(), error)
+                });
+            }
+        } else if let Some(diff) = expected.diff(&actual) {
+            failures.push_str(&format!("{}:\n{}\n", path.display(), diff));
+        }
+    }
+
+    if !failures.is_empty() {
+        panic!(
+            "transcripts don't match, rerun with {}=1 to accept the changes\n\n{}",
+            BLESS_VAR, failures
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::test_support::Adder;
+
+    const TEXT: &str = ";; numbers add up
+1 2
+;;=> 1
+;;=> 3
+
+(4
+  ;; inside
+  5)
+;;=> 12
+(oops)
+;;!! \"NotANumber\"
+";
+
+    #[test]
+    fn test_round_trip() {
+        assert_eq!(TEXT, Transcript::parse(TEXT).to_string());
+    }
+
+    #[test]
+    fn test_replay_matches() {
+        let transcript = Transcript::parse(TEXT);
+        let actual = transcript.replay(&mut Adder::new());
+        assert_eq!(transcript, actual);
+        assert_eq!(None, transcript.diff(&actual));
+    }
+
+    #[test]
+    fn test_replay_mismatch() {
+        let transcript = Transcript::parse("(2 2)\n;;=> 5\n");
+        let actual = transcript.replay(&mut Adder::new());
+        assert_eq!("(2 2)\n;;=> 4\n", actual.to_string());
+        assert_eq!(
+            Some("line 1: (2 2)\n- ;;=> 5\n+ ;;=> 4\n".to_string()),
+            transcript.diff(&actual)
+        );
+    }
+
+    #[test]
+    fn test_replay_fn() {
+        let transcript = Transcript::parse("1\n(a b)\n");
+        let actual = transcript.replay_fn(|sexp| {
+            if sexp.is_atom() {
+                Some(format!("{}", sexp))
+            } else {
+                None
+            }
+        });
+        assert_eq!("1\n;;=> 1\n(a b)\n", actual.to_string());
+    }
+
+    #[test]
+    fn test_recording_replays() {
+        let path = env::temp_dir().join(format!("dcpl-recording-{}", std::process::id()));
+        let mut recording = Recording::create(&path, "Adder").unwrap();
+        recording.record("(4\n  4)", &value_lines("8")).unwrap();
+        recording
+            .record("(oops)", &error_lines("\"NotANumber\""))
+            .unwrap();
+        drop(recording);
+
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            ";; Adder session transcript\n(4\n  4)\n;;=> 8\n(oops)\n;;!! \"NotANumber\"\n",
+            text
+        );
+        let transcript = Transcript::parse(&text);
+        assert_eq!(None, transcript.diff(&transcript.replay(&mut Adder::new())));
+    }
+
+    #[test]
+    fn test_replay_parse_error() {
+        let transcript = Transcript::parse(")\n");
+        let actual = transcript.replay(&mut Adder::new());
+        assert!(actual.to_string().starts_with(")\n;;!! invalid input: "));
+    }
+}